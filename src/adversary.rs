@@ -0,0 +1,135 @@
+use {
+    rand::{seq::SliceRandom, Rng},
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        num::{ParseFloatError, ParseIntError},
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+// Default number of attack packets each malicious node sends per gossip
+// round.
+const DEFAULT_ATTACK_RATE: usize = 16;
+
+#[derive(Debug, Error)]
+pub enum AdversaryError {
+    #[error("invalid adversary spec: {0}")]
+    InvalidSpec(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+}
+
+// Malicious behaviors which nodes exhibit on top of honest gossip. Attack
+// packets are sent to random gossip peers, regardless of the active set.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Attack {
+    // Floods peers with push packets of stale crds values of other origins.
+    Spam,
+    // Replays crds values with their previous ordinals, i.e. values which
+    // have been just overwritten.
+    Replay,
+    // Pushes current crds values with the from field forged as a random
+    // honest node, so that receivers credit or prune the wrong node.
+    Spoof,
+    // Sends prune messages naming random honest origins, with the from field
+    // forged as a random honest node. Unlike solana-gossip, prunes are not
+    // signed here, so this tests the active set if that check were bypassed.
+    Prune,
+}
+
+// Malicious nodes as specified on the command line, before nodes are
+// sampled.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct AdversarySpec {
+    pub attack: Attack,
+    // Fraction of the cluster nodes which are malicious.
+    fraction: f64,
+    // Number of attack packets sent by each malicious node per gossip round.
+    pub rate: usize,
+}
+
+impl AdversarySpec {
+    // Returns the malicious nodes.
+    pub fn resolve<R: Rng>(&self, rng: &mut R, nodes: &[Pubkey]) -> Vec<Pubkey> {
+        let amount = (nodes.len() as f64 * self.fraction).round() as usize;
+        nodes.choose_multiple(rng, amount).copied().collect()
+    }
+}
+
+// Parses attack from either of "spam", "replay", "spoof" or "prune".
+impl FromStr for Attack {
+    type Err = AdversaryError;
+
+    fn from_str(attack: &str) -> Result<Self, Self::Err> {
+        match attack {
+            "spam" => Ok(Self::Spam),
+            "replay" => Ok(Self::Replay),
+            "spoof" => Ok(Self::Spoof),
+            "prune" => Ok(Self::Prune),
+            _ => Err(AdversaryError::InvalidSpec(attack.to_string())),
+        }
+    }
+}
+
+// Parses adversary specs of the form:
+//     ATTACK:FRACTION[,RATE]
+// where RATE is the number of attack packets per gossip round, defaulting to
+// 16.
+impl FromStr for AdversarySpec {
+    type Err = AdversaryError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid_spec = || AdversaryError::InvalidSpec(spec.to_string());
+        let (attack, args) = spec.split_once(':').ok_or_else(invalid_spec)?;
+        let args: Vec<_> = args.split(',').map(str::trim).collect();
+        let (fraction, rate) = match args[..] {
+            [fraction] => (fraction, DEFAULT_ATTACK_RATE),
+            [fraction, rate] => (fraction, usize::from_str(rate)?),
+            _ => return Err(invalid_spec()),
+        };
+        let fraction = f64::from_str(fraction)?;
+        if !(0.0..=1.0).contains(&fraction) {
+            return Err(invalid_spec());
+        }
+        Ok(Self {
+            attack: Attack::from_str(attack)?,
+            fraction,
+            rate,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng, std::iter::repeat_with};
+
+    #[test]
+    fn test_parse_adversary_spec() {
+        assert_eq!(
+            AdversarySpec::from_str("spoof:0.1").unwrap(),
+            AdversarySpec {
+                attack: Attack::Spoof,
+                fraction: 0.1,
+                rate: DEFAULT_ATTACK_RATE,
+            }
+        );
+        let spec = AdversarySpec::from_str("prune:0.25,4").unwrap();
+        assert_eq!(spec.attack, Attack::Prune);
+        assert_eq!(spec.rate, 4);
+        let mut rng = ChaChaRng::from_seed([89u8; 32]);
+        let nodes: Vec<_> = repeat_with(Pubkey::new_unique).take(20).collect();
+        assert_eq!(spec.resolve(&mut rng, &nodes).len(), 5);
+        for spec in [
+            "spam",
+            "spam:1.5",
+            "spam:0.1,x",
+            "flood:0.1",
+            "replay:0.1,2,3",
+        ] {
+            assert!(AdversarySpec::from_str(spec).is_err(), "{spec}");
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use {
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        num::ParseFloatError,
+        str::FromStr,
+        sync::Mutex,
+        time::{Duration, Instant},
+    },
+    thiserror::Error,
+};
+
+const DEFAULT_QUEUE_SIZE_KB: f64 = 1024.0;
+
+#[derive(Debug, Error)]
+pub enum BandwidthError {
+    #[error("invalid bandwidth spec: {0}")]
+    InvalidSpec(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+}
+
+// Egress and ingress bandwidth cap of each node, with a bounded queue in
+// front of each link.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bandwidth {
+    // Bytes per second.
+    pub egress: f64,
+    pub ingress: f64,
+    // Max number of bytes buffered in each queue; packets arriving at a full
+    // queue are dropped.
+    pub queue_size: usize,
+}
+
+// FIFO queue drained at a fixed rate, tracked only by the time at which its
+// current backlog is cleared.
+#[derive(Debug, Default)]
+struct Queue {
+    busy_until: Option<Instant>,
+}
+
+// Egress and ingress queues of each node.
+pub(crate) struct Links {
+    bandwidth: Bandwidth,
+    egress: HashMap<Pubkey, Mutex<Queue>>,
+    ingress: HashMap<Pubkey, Mutex<Queue>>,
+}
+
+impl Queue {
+    // Enqueues a packet arriving at the given time, returning the time its
+    // last byte is drained, or None if the packet is tail-dropped.
+    fn push(&mut self, now: Instant, size: usize, rate: f64, capacity: usize) -> Option<Instant> {
+        let start = self
+            .busy_until
+            .map_or(now, |busy_until| busy_until.max(now));
+        let backlog = (start - now).as_secs_f64() * rate;
+        if backlog + size as f64 > capacity as f64 {
+            return None;
+        }
+        let busy_until = start + Duration::from_secs_f64(size as f64 / rate);
+        self.busy_until = Some(busy_until);
+        Some(busy_until)
+    }
+}
+
+impl Links {
+    pub(crate) fn new<'a, I>(bandwidth: Bandwidth, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Pubkey>,
+    {
+        let nodes: Vec<_> = nodes.into_iter().copied().collect();
+        let make_queues = || nodes.iter().map(|&node| (node, Mutex::default())).collect();
+        Self {
+            bandwidth,
+            egress: make_queues(),
+            ingress: make_queues(),
+        }
+    }
+
+    // Returns the time the packet leaves the sending node, or None if its
+    // egress queue is full.
+    pub(crate) fn send(&self, now: Instant, from: &Pubkey, size: usize) -> Option<Instant> {
+        let queue = self.egress.get(from)?;
+        let mut queue = queue.lock().unwrap();
+        queue.push(now, size, self.bandwidth.egress, self.bandwidth.queue_size)
+    }
+
+    // Returns the time the packet is received by the node, or None if its
+    // ingress queue is full. Packets are queued in the order they are sent,
+    // which only approximates the order they arrive at the node.
+    pub(crate) fn recv(&self, arrival: Instant, node: &Pubkey, size: usize) -> Option<Instant> {
+        let queue = self.ingress.get(node)?;
+        let mut queue = queue.lock().unwrap();
+        queue.push(
+            arrival,
+            size,
+            self.bandwidth.ingress,
+            self.bandwidth.queue_size,
+        )
+    }
+}
+
+// Parses bandwidth specs of the form:
+//     EGRESS,INGRESS[,QUEUE_SIZE]
+// where EGRESS and INGRESS are in Mbps and QUEUE_SIZE is in KB, defaulting to
+// 1024KB.
+impl FromStr for Bandwidth {
+    type Err = BandwidthError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let args: Vec<_> = spec.split(',').map(str::trim).collect();
+        let (egress, ingress, queue_size) = match args[..] {
+            [egress, ingress] => (egress, ingress, DEFAULT_QUEUE_SIZE_KB),
+            [egress, ingress, queue_size] => (egress, ingress, f64::from_str(queue_size)?),
+            _ => return Err(BandwidthError::InvalidSpec(spec.to_string())),
+        };
+        // Mbps to bytes per second.
+        let egress = f64::from_str(egress)? * 1e6 / 8.0;
+        let ingress = f64::from_str(ingress)? * 1e6 / 8.0;
+        if !(egress > 0.0 && ingress > 0.0 && queue_size > 0.0) {
+            return Err(BandwidthError::InvalidSpec(spec.to_string()));
+        }
+        Ok(Self {
+            egress,
+            ingress,
+            queue_size: (queue_size * 1024.0) as usize,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_links() {
+        let bandwidth = Bandwidth::from_str("8,0.8,10").unwrap();
+        assert_eq!(
+            bandwidth,
+            Bandwidth {
+                egress: 1e6,
+                ingress: 1e5,
+                queue_size: 10240,
+            }
+        );
+        let (from, node) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let links = Links::new(bandwidth, &[from, node]);
+        let now = Instant::now();
+        // 1000 bytes take 1ms on egress and 10ms on ingress.
+        let departures: Vec<_> = (0..20).map(|_| links.send(now, &from, 1000)).collect();
+        assert_eq!(departures[0], Some(now + Duration::from_millis(1)));
+        assert_eq!(departures[9], Some(now + Duration::from_millis(10)));
+        // Queue is full after 10 packets.
+        assert!(departures[10..].iter().all(Option::is_none));
+        assert_eq!(
+            links.recv(now, &node, 1000),
+            Some(now + Duration::from_millis(10))
+        );
+        assert_eq!(
+            links.recv(now, &node, 1000),
+            Some(now + Duration::from_millis(20))
+        );
+        // Backlog drains over time.
+        let later = now + Duration::from_millis(15);
+        assert_eq!(
+            links.recv(later, &node, 1000),
+            Some(now + Duration::from_millis(30))
+        );
+        assert_eq!(
+            links.send(later, &from, 1000),
+            Some(later + Duration::from_millis(1))
+        );
+        for spec in ["8", "8,0", "8,1,0", "8,1,2,3", "x,1"] {
+            assert!(Bandwidth::from_str(spec).is_err(), "{spec}");
+        }
+    }
+}
@@ -0,0 +1,438 @@
+use {
+    crate::{
+        gossip::{get_crds_table, CrdsEntry, Node},
+        partition::{PartitionError, PartitionTime},
+        propagation::Histogram,
+    },
+    rand::{seq::SliceRandom, Rng},
+    solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
+    std::{
+        collections::{HashMap, HashSet},
+        fs::File,
+        io::{BufRead, BufReader, BufWriter, Write},
+        num::ParseFloatError,
+        path::Path,
+        str::FromStr,
+        time::Duration,
+    },
+    thiserror::Error,
+};
+
+// A restarted node has caught up once the fraction of up to date entries in
+// its crds table is within this ratio of the mean across other online nodes.
+const CATCH_UP_THRESHOLD: f64 = 0.99;
+
+#[derive(Debug, Error)]
+pub enum ChurnError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid churn spec: {0}")]
+    InvalidSpec(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParsePubkeyError(#[from] ParsePubkeyError),
+    #[error(transparent)]
+    PartitionError(#[from] PartitionError),
+}
+
+// Time window during which a node is offline and packets sent to it are
+// dropped. Nodes come back online with empty crds tables, active sets and
+// received caches as if restarted, and publish their own values anew; nodes
+// which leave do not come back.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Downtime {
+    pub node: Pubkey,
+    // Start and end of the downtime relative to the simulation epoch.
+    pub start: Duration,
+    pub end: Option<Duration>,
+}
+
+// Churn as specified on the command line, before nodes and times are
+// sampled.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ChurnSpec {
+    // Each node restarts at the given rate per minute, and stays offline for
+    // the given duration.
+    Restart { rate: f64, downtime: PartitionTime },
+    // Fraction of nodes which leave or join the cluster at uniformly random
+    // times during the run.
+    Leave(f64),
+    Join(f64),
+    Schedule(Vec<(Pubkey, PartitionTime, Option<PartitionTime>)>),
+}
+
+// Downtimes of each node, sorted by start time.
+#[derive(Debug, Default)]
+pub struct Churn {
+    downtimes: HashMap<Pubkey, Vec<(Duration, Option<Duration>)>>,
+}
+
+// Periodically samples how up to date the crds tables of restarted nodes are,
+// in order to measure how long they take to catch up with the cluster.
+pub struct ChurnMonitor {
+    churn: Churn,
+    // Restarts not yet reached by the simulation, sorted by time.
+    restarts: Vec<(Duration, Pubkey)>,
+    num_restarts: usize,
+    // Restarted nodes which have not caught up yet.
+    catching_up: HashMap<Pubkey, /*restart:*/ Duration>,
+    catch_up_times: Histogram,
+}
+
+#[derive(Clone, Debug)]
+pub struct ChurnReport {
+    // Number of restarts and late joins during the simulation.
+    pub num_restarts: usize,
+    // Time from restart until the node caught up with the cluster; restarts
+    // which did not catch up within the simulation are not included.
+    pub catch_up_times: Histogram,
+}
+
+impl Churn {
+    pub fn new(downtimes: &[Downtime]) -> Self {
+        let mut churn = Self::default();
+        for downtime in downtimes {
+            churn
+                .downtimes
+                .entry(downtime.node)
+                .or_default()
+                .push((downtime.start, downtime.end));
+        }
+        for downtimes in churn.downtimes.values_mut() {
+            downtimes.sort_unstable();
+        }
+        churn
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.downtimes.is_empty()
+    }
+
+    pub fn nodes(&self) -> HashSet<Pubkey> {
+        self.downtimes.keys().copied().collect()
+    }
+
+    pub(crate) fn is_offline(&self, elapsed: Duration, node: &Pubkey) -> bool {
+        let Some(downtimes) = self.downtimes.get(node) else {
+            return false;
+        };
+        downtimes
+            .iter()
+            .any(|&(start, end)| start <= elapsed && end.map_or(true, |end| elapsed < end))
+    }
+
+    // Returns the latest time in (since, elapsed] at which the node came back
+    // online.
+    pub(crate) fn get_restart(
+        &self,
+        since: Duration,
+        elapsed: Duration,
+        node: &Pubkey,
+    ) -> Option<Duration> {
+        self.downtimes
+            .get(node)?
+            .iter()
+            .filter_map(|&(_, end)| end)
+            .filter(|&end| since < end && end <= elapsed)
+            .max()
+    }
+}
+
+impl ChurnSpec {
+    pub fn resolve<R: Rng>(
+        &self,
+        rng: &mut R,
+        nodes: &[Pubkey],
+        gossip_interval: Duration,
+        run_duration: Duration,
+    ) -> Vec<Downtime> {
+        match self {
+            Self::Restart { rate, downtime } => {
+                let downtime = downtime.resolve(gossip_interval);
+                let mut downtimes = Vec::new();
+                for &node in nodes {
+                    // Restarts are a poisson process interleaved with
+                    // downtimes.
+                    let mut start = Duration::ZERO;
+                    loop {
+                        let gap = -(1.0 - rng.gen::<f64>()).ln() * 60.0 / rate;
+                        let gap = Duration::from_secs_f64(gap.min(run_duration.as_secs_f64()));
+                        start += truncate_millis(gap);
+                        if start >= run_duration {
+                            break;
+                        }
+                        downtimes.push(Downtime {
+                            node,
+                            start,
+                            end: Some(start + downtime),
+                        });
+                        start += downtime;
+                    }
+                }
+                downtimes
+            }
+            Self::Leave(fraction) => choose_nodes(rng, nodes, *fraction)
+                .into_iter()
+                .map(|node| Downtime {
+                    node,
+                    start: truncate_millis(run_duration.mul_f64(rng.gen())),
+                    end: None,
+                })
+                .collect(),
+            Self::Join(fraction) => choose_nodes(rng, nodes, *fraction)
+                .into_iter()
+                .map(|node| Downtime {
+                    node,
+                    start: Duration::ZERO,
+                    end: Some(truncate_millis(run_duration.mul_f64(rng.gen()))),
+                })
+                .collect(),
+            Self::Schedule(schedule) => schedule
+                .iter()
+                .map(|(node, start, end)| Downtime {
+                    node: *node,
+                    start: start.resolve(gossip_interval),
+                    end: end.map(|end| end.resolve(gossip_interval)),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl ChurnMonitor {
+    pub fn new(churn: Churn) -> Self {
+        let mut restarts: Vec<_> = churn
+            .downtimes
+            .iter()
+            .flat_map(|(node, downtimes)| {
+                downtimes
+                    .iter()
+                    .filter_map(move |&(_, end)| Some((end?, *node)))
+            })
+            .collect();
+        // Reversed so that the next restart can be popped from the end.
+        restarts.sort_unstable_by(|a, b| b.cmp(a));
+        Self {
+            churn,
+            restarts,
+            num_restarts: 0,
+            catching_up: HashMap::default(),
+            catch_up_times: Histogram::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.churn.is_empty()
+    }
+
+    pub fn nodes(&self) -> HashSet<Pubkey> {
+        self.churn.nodes()
+    }
+
+    pub fn sample(&mut self, elapsed: Duration, nodes: &[&Node]) {
+        while let Some(&(restart, node)) = self.restarts.last() {
+            if restart > elapsed {
+                break;
+            }
+            self.restarts.pop();
+            self.num_restarts += 1;
+            // A node restarting again before catching up starts over.
+            self.catching_up.insert(node, restart);
+        }
+        self.catching_up
+            .retain(|node, _| !self.churn.is_offline(elapsed, node));
+        if self.catching_up.is_empty() {
+            return;
+        }
+        let table = get_crds_table(nodes.iter().copied());
+        let get_freshness = |node: &Node| {
+            let node_table = node.table();
+            let num_hits = table
+                .iter()
+                .filter(|(key, ordinal)| {
+                    node_table.get(key).map(CrdsEntry::ordinal) == Some(**ordinal)
+                })
+                .count();
+            num_hits as f64 / table.len().max(1) as f64
+        };
+        let (num_nodes, freshness) = nodes
+            .iter()
+            .filter(|node| !self.catching_up.contains_key(&node.pubkey()))
+            .filter(|node| !self.churn.is_offline(elapsed, &node.pubkey()))
+            .fold((0usize, 0.0), |(num_nodes, freshness), node| {
+                (num_nodes + 1, freshness + get_freshness(node))
+            });
+        let freshness = freshness / num_nodes.max(1) as f64;
+        for node in nodes {
+            let Some(&restart) = self.catching_up.get(&node.pubkey()) else {
+                continue;
+            };
+            if get_freshness(node) >= freshness * CATCH_UP_THRESHOLD {
+                self.catch_up_times.add(elapsed - restart);
+                self.catching_up.remove(&node.pubkey());
+            }
+        }
+    }
+
+    pub fn get_report(&self) -> ChurnReport {
+        ChurnReport {
+            num_restarts: self.num_restarts,
+            catch_up_times: self.catch_up_times.clone(),
+        }
+    }
+}
+
+// Parses churn specs of the form:
+//     restart:RATE,DOWNTIME | leave:FRACTION | join:FRACTION | file:PATH
+// where RATE is the positive number of restarts per node per minute, DOWNTIME
+// is non-zero in one of ms, s or r (gossip rounds) units, and the file has
+// lines of the form PUBKEY,START[,END], as written by write_downtimes.
+impl FromStr for ChurnSpec {
+    type Err = ChurnError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid_spec = || ChurnError::InvalidSpec(spec.to_string());
+        let (kind, args) = spec.split_once(':').ok_or_else(invalid_spec)?;
+        let args: Vec<_> = args.split(',').map(str::trim).collect();
+        let parse_fraction = |fraction| {
+            let fraction = f64::from_str(fraction)?;
+            if !(0.0..=1.0).contains(&fraction) {
+                return Err(invalid_spec());
+            }
+            Ok(fraction)
+        };
+        match (kind, &args[..]) {
+            ("restart", [rate, downtime]) => {
+                let rate = f64::from_str(rate)?;
+                if !rate.is_finite() || rate <= 0.0 {
+                    return Err(invalid_spec());
+                }
+                let downtime = PartitionTime::from_str(downtime)?;
+                if matches!(
+                    downtime,
+                    PartitionTime::Duration(Duration::ZERO) | PartitionTime::Rounds(0)
+                ) {
+                    return Err(invalid_spec());
+                }
+                Ok(Self::Restart { rate, downtime })
+            }
+            ("leave", [fraction]) => Ok(Self::Leave(parse_fraction(fraction)?)),
+            ("join", [fraction]) => Ok(Self::Join(parse_fraction(fraction)?)),
+            ("file", [path]) => Ok(Self::Schedule(read_schedule(path)?)),
+            _ => Err(invalid_spec()),
+        }
+    }
+}
+
+// Writes downtimes in the format accepted by file:PATH churn specs, so that
+// randomly generated churn can be replayed.
+pub fn write_downtimes<P: AsRef<Path>>(path: P, downtimes: &[Downtime]) -> std::io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    for downtime in downtimes {
+        write!(writer, "{},{}ms", downtime.node, downtime.start.as_millis())?;
+        if let Some(end) = downtime.end {
+            write!(writer, ",{}ms", end.as_millis())?;
+        }
+        writeln!(writer)?;
+    }
+    writer.flush()
+}
+
+fn read_schedule(
+    path: &str,
+) -> Result<Vec<(Pubkey, PartitionTime, Option<PartitionTime>)>, ChurnError> {
+    let mut schedule = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let (node, start, end) = match fields[..] {
+            [node, start] => (node, start, None),
+            [node, start, end] => (node, start, Some(PartitionTime::from_str(end)?)),
+            _ => return Err(ChurnError::InvalidSpec(line.to_string())),
+        };
+        schedule.push((
+            Pubkey::from_str(node)?,
+            PartitionTime::from_str(start)?,
+            end,
+        ));
+    }
+    Ok(schedule)
+}
+
+// Randomly generated times are truncated to milliseconds so that schedules
+// written by write_downtimes are replayed exactly.
+fn truncate_millis(duration: Duration) -> Duration {
+    Duration::from_millis(duration.as_millis() as u64)
+}
+
+fn choose_nodes<R: Rng>(rng: &mut R, nodes: &[Pubkey], fraction: f64) -> Vec<Pubkey> {
+    let amount = (nodes.len() as f64 * fraction).round() as usize;
+    let mut nodes: Vec<_> = nodes.choose_multiple(rng, amount).copied().collect();
+    // Sorted so that times drawn for each node do not depend on the
+    // iteration order of choose_multiple.
+    nodes.sort_unstable();
+    nodes
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng, std::iter::repeat_with};
+
+    #[test]
+    fn test_churn() {
+        let mut rng = ChaChaRng::from_seed([83u8; 32]);
+        let mut nodes: Vec<_> = repeat_with(Pubkey::new_unique).take(20).collect();
+        nodes.sort_unstable();
+        let gossip_interval = Duration::from_millis(200);
+        let run_duration = Duration::from_secs(600);
+        let spec = ChurnSpec::from_str("restart:0.1,50r").unwrap();
+        let downtimes = spec.resolve(&mut rng, &nodes, gossip_interval, run_duration);
+        // 20 nodes restarting once every 10 minutes on average.
+        assert!((10..=40).contains(&downtimes.len()), "{}", downtimes.len());
+        for downtime in &downtimes {
+            assert!(downtime.start < run_duration);
+            assert_eq!(downtime.end, Some(downtime.start + Duration::from_secs(10)));
+        }
+        let churn = Churn::new(&downtimes);
+        let Downtime { node, start, end } = downtimes[0].clone();
+        let end = end.unwrap();
+        assert!(!churn.is_offline(start - Duration::from_millis(1), &node));
+        assert!(churn.is_offline(start, &node));
+        assert!(!churn.is_offline(end, &node));
+        assert_eq!(churn.get_restart(start, end, &node), Some(end));
+        assert_eq!(churn.get_restart(end, end + gossip_interval, &node), None);
+        let spec = ChurnSpec::from_str("leave:0.25").unwrap();
+        let downtimes = spec.resolve(&mut rng, &nodes, gossip_interval, run_duration);
+        assert_eq!(downtimes.len(), 5);
+        let churn = Churn::new(&downtimes);
+        assert!(churn.is_offline(run_duration, &downtimes[0].node));
+        assert_eq!(churn.nodes().len(), 5);
+        // Schedules written to a file are replayed exactly.
+        let path = std::env::temp_dir().join(format!("cluster-mocks-churn-{}", std::process::id()));
+        write_downtimes(&path, &downtimes).unwrap();
+        let spec = ChurnSpec::from_str(&format!("file:{}", path.display()));
+        std::fs::remove_file(&path).unwrap();
+        let replay = spec
+            .unwrap()
+            .resolve(&mut rng, &nodes, gossip_interval, run_duration);
+        assert_eq!(replay, downtimes);
+        for spec in [
+            "restart:0,10s",
+            "restart:1,10",
+            "restart:inf,10s",
+            "restart:NaN,10s",
+            "restart:1,0ms",
+            "restart:1,0r",
+            "join:2",
+            "leave",
+            "crash:0.1",
+        ] {
+            assert!(ChurnSpec::from_str(spec).is_err(), "{spec}");
+        }
+    }
+}
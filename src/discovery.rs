@@ -0,0 +1,122 @@
+use {
+    crate::{gossip::Node, propagation::Histogram},
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::{HashMap, HashSet},
+        time::Duration,
+    },
+};
+
+// Fractions of the cluster each node discovers, for which the time taken is
+// reported.
+pub const DISCOVERY_FRACTIONS: [f64; 3] = [0.5, 0.9, 1.0];
+
+// Tracks how long nodes bootstrapped from entrypoints take to discover the
+// rest of the cluster. A node has discovered a peer once it holds a crds
+// value of the peer in its table.
+#[derive(Default)]
+pub struct DiscoveryMonitor {
+    enabled: bool,
+    // Number of DISCOVERY_FRACTIONS each node has reached so far.
+    progress: HashMap<Pubkey, usize>,
+    discovery_times: [Histogram; DISCOVERY_FRACTIONS.len()],
+    // Fraction of the cluster discovered by each node as of the last sample.
+    discovered: Vec<f64>,
+}
+
+impl DiscoveryMonitor {
+    pub fn new(enabled: bool) -> Self {
+        Self {
+            enabled,
+            ..Self::default()
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        !self.enabled
+    }
+
+    // Returns time taken to discover each of DISCOVERY_FRACTIONS of the
+    // cluster, only counting nodes which got there.
+    pub fn discovery_times(&self) -> &[Histogram; DISCOVERY_FRACTIONS.len()] {
+        &self.discovery_times
+    }
+
+    // Returns the fraction of the cluster discovered by each node as of the
+    // last sample.
+    pub fn discovered(&self) -> &[f64] {
+        &self.discovered
+    }
+
+    pub fn sample(&mut self, elapsed: Duration, nodes: &[&Node]) {
+        if !self.enabled {
+            return;
+        }
+        let num_peers = nodes.len().saturating_sub(1).max(1);
+        self.discovered.clear();
+        for node in nodes {
+            let pubkey = node.pubkey();
+            let num_discovered = node
+                .table()
+                .keys()
+                .map(|key| key.origin())
+                .filter(|origin| origin != &pubkey)
+                .collect::<HashSet<_>>()
+                .len();
+            let discovered = num_discovered as f64 / num_peers as f64;
+            self.discovered.push(discovered);
+            let progress = self.progress.entry(pubkey).or_default();
+            while *progress < DISCOVERY_FRACTIONS.len()
+                && discovered >= DISCOVERY_FRACTIONS[*progress]
+            {
+                self.discovery_times[*progress].add(elapsed);
+                *progress += 1;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::gossip::tests::{insert_entry, make_cluster, make_entry},
+    };
+
+    #[test]
+    fn test_discovery_monitor() {
+        let (_rng, now, mut nodes, _stakes, _router) = make_cluster(71, 5);
+        let pubkeys: Vec<Pubkey> = nodes.iter().map(Node::pubkey).collect();
+        // Own values do not count towards discovery.
+        for (node, pubkey) in nodes.iter_mut().zip(&pubkeys) {
+            insert_entry(node, *pubkey, make_entry(now, 1));
+        }
+        for pubkey in &pubkeys[2..4] {
+            insert_entry(&mut nodes[1], *pubkey, make_entry(now, 1));
+        }
+        for pubkey in &pubkeys {
+            insert_entry(&mut nodes[2], *pubkey, make_entry(now, 1));
+        }
+        let mut monitor = DiscoveryMonitor::new(true);
+        monitor.sample(Duration::from_secs(1), &nodes.iter().collect::<Vec<_>>());
+        assert_eq!(monitor.discovered(), [0.0, 0.5, 1.0, 0.0, 0.0]);
+        let counts = monitor.discovery_times().iter().map(Histogram::count);
+        assert_eq!(counts.collect::<Vec<_>>(), [2, 1, 1]);
+        // Fractions already reached are not counted again.
+        insert_entry(&mut nodes[1], pubkeys[0], make_entry(now, 1));
+        monitor.sample(Duration::from_secs(2), &nodes.iter().collect::<Vec<_>>());
+        assert_eq!(monitor.discovered(), [0.0, 0.75, 1.0, 0.0, 0.0]);
+        let counts = monitor.discovery_times().iter().map(Histogram::count);
+        assert_eq!(counts.collect::<Vec<_>>(), [2, 1, 1]);
+        insert_entry(&mut nodes[1], pubkeys[4], make_entry(now, 1));
+        monitor.sample(Duration::from_secs(3), &nodes.iter().collect::<Vec<_>>());
+        assert_eq!(monitor.discovered(), [0.0, 1.0, 1.0, 0.0, 0.0]);
+        let counts = monitor.discovery_times().iter().map(Histogram::count);
+        assert_eq!(counts.collect::<Vec<_>>(), [2, 2, 2]);
+        // A disabled monitor does not sample.
+        let mut monitor = DiscoveryMonitor::new(false);
+        monitor.sample(Duration::from_secs(1), &nodes.iter().collect::<Vec<_>>());
+        assert!(monitor.is_empty());
+        assert!(monitor.discovered().is_empty());
+    }
+}
@@ -0,0 +1,257 @@
+use {
+    crate::gossip::{get_crds_table, CrdsEntry, Node, NodeInfo},
+    rand::{seq::SliceRandom, Rng},
+    solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
+    std::{
+        collections::HashSet,
+        num::{ParseFloatError, ParseIntError},
+        str::FromStr,
+        time::Duration,
+    },
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum EclipseError {
+    #[error("invalid eclipse spec: {0}")]
+    InvalidSpec(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+    #[error(transparent)]
+    ParsePubkeyError(#[from] ParsePubkeyError),
+}
+
+// Eclipse attack as specified on the command line, before sybil nodes are
+// added to the cluster.
+#[derive(Clone, Debug, PartialEq)]
+pub struct EclipseSpec {
+    num_nodes: usize,
+    // Fraction of the cluster stake held by sybil nodes once added, split
+    // evenly among them.
+    stake: f64,
+    victim: Option<Pubkey>,
+}
+
+// A coalition of sybil nodes which occupy active-set slots of the victim and
+// selectively drop its traffic: they ignore its pushes and pull requests and
+// never push to it, while gossiping honestly with the rest of the cluster.
+#[derive(Clone, Debug)]
+pub struct Eclipse {
+    pub victim: Pubkey,
+    pub sybils: HashSet<Pubkey>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct EclipseSample {
+    pub elapsed: Duration,
+    // Number of times the victim has rotated its push active set.
+    pub num_rotations: usize,
+    // Fraction of the victim's active-set slots, across all stake-bucket
+    // entries, occupied by sybils.
+    pub sybil_fraction: f64,
+    // Fraction of the most recent crds values the victim has, and the same
+    // averaged over honest nodes other than the victim.
+    pub victim_freshness: f64,
+    pub honest_freshness: f64,
+}
+
+// Tracks the victim's active set and table freshness over time.
+pub struct EclipseMonitor {
+    eclipse: Option<Eclipse>,
+    samples: Vec<EclipseSample>,
+}
+
+impl EclipseSpec {
+    // Returns the sybil nodes to add to the cluster, and the attack targeting
+    // the victim, sampled from the given nodes if not specified.
+    pub fn resolve<R: Rng>(&self, rng: &mut R, nodes: &[NodeInfo]) -> (Vec<NodeInfo>, Eclipse) {
+        let victim = self.victim.unwrap_or_else(|| {
+            let mut pubkeys: Vec<_> = nodes.iter().map(|node| node.pubkey).collect();
+            pubkeys.sort_unstable();
+            *pubkeys.choose(rng).unwrap()
+        });
+        // Stake such that sybils hold the given fraction of the total.
+        let cluster_stake: u64 = nodes.iter().map(|node| node.stake).sum();
+        let stake = cluster_stake as f64 * self.stake / (1.0 - self.stake);
+        let stake = (stake / self.num_nodes as f64) as u64;
+        let sybils: Vec<_> = std::iter::repeat_with(|| NodeInfo {
+            pubkey: Pubkey::new_from_array(rng.gen()),
+            stake,
+            shred_version: None,
+        })
+        .take(self.num_nodes)
+        .collect();
+        let eclipse = Eclipse {
+            victim,
+            sybils: sybils.iter().map(|node| node.pubkey).collect(),
+        };
+        (sybils, eclipse)
+    }
+}
+
+impl EclipseMonitor {
+    pub fn new(eclipse: Option<Eclipse>) -> Self {
+        Self {
+            eclipse,
+            samples: Vec::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.eclipse.is_none()
+    }
+
+    pub fn eclipse(&self) -> Option<&Eclipse> {
+        self.eclipse.as_ref()
+    }
+
+    pub fn samples(&self) -> &[EclipseSample] {
+        &self.samples
+    }
+
+    pub fn sample(&mut self, elapsed: Duration, nodes: &[&Node]) {
+        let Some(eclipse) = &self.eclipse else {
+            return;
+        };
+        let Some(victim) = nodes.iter().find(|node| node.pubkey() == eclipse.victim) else {
+            return;
+        };
+        let table = get_crds_table(nodes.iter().copied());
+        let get_freshness = |node: &Node| {
+            let node_table = node.table();
+            let num_hits = table
+                .iter()
+                .filter(|(key, ordinal)| {
+                    node_table.get(key).map(CrdsEntry::ordinal) == Some(**ordinal)
+                })
+                .count();
+            num_hits as f64 / table.len().max(1) as f64
+        };
+        let (num_nodes, honest_freshness) = nodes
+            .iter()
+            .filter(|node| node.pubkey() != eclipse.victim)
+            .filter(|node| !eclipse.sybils.contains(&node.pubkey()))
+            .fold((0usize, 0.0), |(num_nodes, freshness), node| {
+                (num_nodes + 1, freshness + get_freshness(node))
+            });
+        let active_set = victim.active_set_nodes();
+        let num_sybils = active_set
+            .iter()
+            .filter(|node| eclipse.sybils.contains(node))
+            .count();
+        self.samples.push(EclipseSample {
+            elapsed,
+            num_rotations: victim.num_rotations(),
+            sybil_fraction: num_sybils as f64 / active_set.len().max(1) as f64,
+            victim_freshness: get_freshness(victim),
+            honest_freshness: honest_freshness / num_nodes.max(1) as f64,
+        });
+    }
+}
+
+// Parses eclipse specs of the form:
+//     NUM_NODES[,STAKE[,VICTIM]]
+// where STAKE is the fraction of the cluster stake held by the sybil nodes,
+// defaulting to 0, and VICTIM is the targeted pubkey, defaulting to a random
+// node.
+impl FromStr for EclipseSpec {
+    type Err = EclipseError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let args: Vec<_> = spec.split(',').map(str::trim).collect();
+        let (num_nodes, stake, victim) = match args[..] {
+            [num_nodes] => (num_nodes, "0", None),
+            [num_nodes, stake] => (num_nodes, stake, None),
+            [num_nodes, stake, victim] => (num_nodes, stake, Some(Pubkey::from_str(victim)?)),
+            _ => return Err(EclipseError::InvalidSpec(spec.to_string())),
+        };
+        let num_nodes = usize::from_str(num_nodes)?;
+        let stake = f64::from_str(stake)?;
+        if num_nodes == 0 || !(0.0..1.0).contains(&stake) {
+            return Err(EclipseError::InvalidSpec(spec.to_string()));
+        }
+        Ok(Self {
+            num_nodes,
+            stake,
+            victim,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::gossip::{
+            tests::{make_cluster, make_config},
+            Config,
+        },
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
+    };
+
+    #[test]
+    fn test_eclipse_spec() {
+        let mut rng = ChaChaRng::from_seed([97u8; 32]);
+        let nodes: Vec<_> = (0..20)
+            .map(|_| NodeInfo {
+                pubkey: Pubkey::new_unique(),
+                stake: 1000,
+                shred_version: None,
+            })
+            .collect();
+        let spec = EclipseSpec::from_str("10,0.2").unwrap();
+        let (sybils, eclipse) = spec.resolve(&mut rng, &nodes);
+        assert_eq!(sybils.len(), 10);
+        assert_eq!(eclipse.sybils.len(), 10);
+        // 5000 stake is 20% of the resulting 25000 total.
+        assert!(sybils.iter().all(|node| node.stake == 500));
+        assert!(nodes.iter().any(|node| node.pubkey == eclipse.victim));
+        let victim = nodes[3].pubkey;
+        let spec = EclipseSpec::from_str(&format!("4,0,{victim}")).unwrap();
+        let (sybils, eclipse) = spec.resolve(&mut rng, &nodes);
+        assert_eq!(eclipse.victim, victim);
+        assert!(sybils.iter().all(|node| node.stake == 0));
+        for spec in ["0", "10,1", "10,0.2,x", "x", "1,0,x,y"] {
+            assert!(EclipseSpec::from_str(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_eclipse_monitor() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(101, 8);
+        let config = Config {
+            rotate_active_set_rounds: 1,
+            push_active_set_size: 10,
+            refresh_rate: 1.0,
+            ..make_config()
+        };
+        let eclipse = Eclipse {
+            victim: nodes[0].pubkey(),
+            sybils: nodes[6..].iter().map(Node::pubkey).collect(),
+        };
+        let mut monitor = EclipseMonitor::new(Some(eclipse));
+        for k in 0..2 {
+            let now = now + config.gossip_interval * k;
+            nodes[0]
+                .run_gossip(&mut rng, now, &config, &stakes, &router)
+                .unwrap();
+            let nodes: Vec<&Node> = nodes.iter().collect();
+            monitor.sample(config.gossip_interval * k, &nodes);
+        }
+        // Every stake-bucket entry holds all 7 peers of the victim, 2 of
+        // which are sybils, and the active set is rotated each round.
+        let samples = monitor.samples();
+        assert_eq!(samples.len(), 2);
+        for (k, sample) in samples.iter().enumerate() {
+            assert_eq!(sample.num_rotations, k + 1);
+            assert!((sample.sybil_fraction - 2.0 / 7.0).abs() < 1e-9);
+            // Only the victim has its own values.
+            assert_eq!(sample.victim_freshness, 1.0);
+            assert_eq!(sample.honest_freshness, 0.0);
+        }
+        assert!(EclipseMonitor::new(None).is_empty());
+    }
+}
@@ -1,7 +1,8 @@
 use {
     clap::{crate_description, crate_name, App, Arg},
     log::info,
-    rand::Rng,
+    rand::{Rng, SeedableRng},
+    rand_chacha::ChaChaRng,
     std::collections::VecDeque,
 };
 
@@ -13,6 +14,7 @@ struct Config {
     bounce_back: f64,
     cluster_size: usize,
     num_rounds: usize,
+    seed: u64,
 }
 
 fn run_fanout<R: Rng>(rng: &mut R, config: &Config) {
@@ -110,6 +112,12 @@ fn main() {
                 .default_value("10000")
                 .help("number of rounds to simulate"),
         )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("rng seed to reproduce a run; random if not specified"),
+        )
         .get_matches();
     let config = {
         let gossip_push_fanout = matches.value_of_t_or_exit("gossip_push_fanout");
@@ -121,9 +129,12 @@ fn main() {
             bounce_back: matches.value_of_t_or_exit("bounce_back"),
             cluster_size: matches.value_of_t_or_exit("cluster_size"),
             num_rounds: matches.value_of_t_or_exit("num_rounds"),
+            seed: matches
+                .value_of_t("seed")
+                .unwrap_or_else(|_| rand::thread_rng().gen()),
         }
     };
     info!("config: {:#?}", config);
-    let mut rng = rand::thread_rng();
+    let mut rng = ChaChaRng::seed_from_u64(config.seed);
     run_fanout(&mut rng, &config);
 }
@@ -1,19 +1,43 @@
 use {
-    crate::{push_active_set::PushActiveSet, received_cache::ReceivedCache, Error, Router},
+    crate::{
+        adversary::Attack,
+        peer_sampler::{get_weights, PeerSampler, StakeBucket, DEFAULT_BUCKET_EXPONENT},
+        ping_cache::PingCache,
+        propagation::{Histogram, PropagationStats},
+        prune_policy::{PrunePolicy, StakeThreshold},
+        push_active_set::{get_stake_bucket, make_bloom_filter, PushActiveSet},
+        received_cache::ReceivedCache,
+        snapshot::read_snapshot,
+        stats::NodeStats,
+        synthetic::{make_synthetic_nodes, StakeDistribution},
+        Delivery, Error, Router, WireSize,
+    },
     crossbeam_channel::{Receiver, Sender},
     itertools::Itertools,
     log::{error, info, trace},
-    rand::Rng,
+    rand::{
+        seq::{IteratorRandom, SliceRandom},
+        Rng,
+    },
+    serde::{Deserialize, Serialize},
+    solana_bloom::bloom::Bloom,
     solana_client::{
         rpc_client::RpcClient, rpc_config::RpcGetVoteAccountsConfig,
         rpc_response::RpcVoteAccountStatus,
     },
-    solana_sdk::{commitment_config::CommitmentConfig, pubkey::Pubkey},
+    solana_gossip::weighted_shuffle::WeightedShuffle,
+    solana_sdk::{
+        commitment_config::CommitmentConfig,
+        hash::{hashv, Hash},
+        packet::PACKET_DATA_SIZE,
+        pubkey::Pubkey,
+    },
     std::{
         borrow::Borrow,
         cmp::{Ordering, Reverse},
-        collections::{hash_map::Entry, HashMap, HashSet},
+        collections::{hash_map::Entry, BinaryHeap, HashMap, HashSet},
         iter::{repeat, repeat_with},
+        path::PathBuf,
         str::FromStr,
         sync::Arc,
         time::{Duration, Instant},
@@ -21,20 +45,81 @@ use {
 };
 
 pub(crate) const CRDS_UNIQUE_PUBKEY_CAPACITY: usize = 8192;
-const CRDS_GOSSIP_PRUNE_STAKE_THRESHOLD_PCT: f64 = 0.15;
+const CRDS_GOSSIP_PULL_FALSE_RATE: f64 = 0.1;
+// Number of hash keys pull request bloom filters are sized for.
+const CRDS_FILTER_NUM_KEYS: f64 = 8.0;
+// Maximum number of crds values returned in response to a pull request.
+const CRDS_GOSSIP_PULL_RESPONSE_CAPACITY: usize = 128;
+// Sizes of bincode encoded gossip structures, mirroring solana-gossip.
+const SIGNATURE_SIZE: usize = 64;
+const PUBKEY_SIZE: usize = 32;
+const HASH_SIZE: usize = 32;
+// Enum discriminants are serialized as u32 and vector lengths as u64.
+const TAG_SIZE: usize = 4;
+const LEN_SIZE: usize = 8;
+// Ipv4 socket address: enum discriminant, ip address and port.
+const SOCKET_ADDR_SIZE: usize = TAG_SIZE + 4 + 2;
+// Approximate size of a vote transaction with a compact vote state update.
+const VOTE_TRANSACTION_SIZE: usize = 350;
+// EpochSlots values are filled with compressed slots up to this size.
+const MAX_CRDS_OBJECT_SIZE: usize = 928;
+// Protocol::PushMessage and Protocol::PullResponse, excluding crds values.
+const PUSH_MESSAGE_HEADER_SIZE: usize = TAG_SIZE + PUBKEY_SIZE + LEN_SIZE;
+// Number of honest origins named in each bogus prune message.
+const NUM_BOGUS_PRUNE_ORIGINS: usize = 32;
 
 pub struct Node {
     clock: Instant,
     num_gossip_rounds: usize,
+    // Number of push active set rotations, including those on restarts.
+    num_rotations: usize,
     pubkey: Pubkey,
     stake: u64,
     table: HashMap<CrdsKey, CrdsEntry>,
+    // Largest ordinal of own values as of the last restart. Values published
+    // after the restart are numbered from here so that they supersede those
+    // still held by the rest of the cluster.
+    restart_ordinal: u64,
     active_set: PushActiveSet,
     received_cache: ReceivedCache,
-    receiver: Receiver<Arc<Packet>>,
+    receiver: Receiver<(/*delivery time:*/ Instant, Arc<Packet>)>,
+    // Packets received from the channel but not yet due for delivery.
+    inbox: BinaryHeap<Reverse<InFlight>>,
+    propagation: PropagationStats,
+    stats: NodeStats,
+    // Upserted keys not yet pushed because of the per-round push budget.
+    deferred_keys: Vec<CrdsKey>,
+    // Pull requests received but not yet responded to.
+    pull_requests: Vec<Arc<Packet>>,
+    // Number of origins pruned from each peer, accumulated after warm-up
+    // rounds.
+    num_pruned_origins: HashMap<Pubkey, usize>,
+    // Time sent packets spent in egress and ingress queues, accumulated
+    // after warm-up rounds.
+    queueing_delay: Histogram,
+    // Malicious behaviors of the node, each with the number of attack
+    // packets sent per gossip round.
+    attacks: Vec<(Attack, /*rate:*/ usize)>,
+    // Attack packets sent, accumulated after warm-up rounds.
+    num_attack_packets: usize,
+    // If set, this is a sybil node eclipsing the victim: it drops the
+    // victim's pushes and pull requests and never pushes to it.
+    eclipse_victim: Option<Pubkey>,
+    // Nodes known at start, from which the rest of the cluster is discovered
+    // through crds values. If empty, all staked nodes are known.
+    entrypoints: Vec<Pubkey>,
+    // Peers which answered a ping; only these are admitted into the push
+    // active set. None until the first rotation, or if disabled by config.
+    ping_cache: Option<PingCache>,
+    // Pings received but not yet responded to.
+    pending_pongs: Vec<(Pubkey, /*token:*/ u64)>,
+    // Decides which ingress nodes to prune for each origin.
+    prune_policy: Arc<dyn PrunePolicy>,
+    // Weighs gossip peers when rotating the push active set.
+    peer_sampler: Arc<dyn PeerSampler>,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, Deserialize, Serialize)]
 pub struct Config {
     pub gossip_push_fanout: f64,
     pub gossip_push_wide_fanout: f64,
@@ -42,8 +127,27 @@ pub struct Config {
     pub rotate_active_set_rounds: usize,
     // Min ingress number of nodes to keep when pruning received-cache.
     pub gossip_prune_min_ingress_nodes: usize,
-    // TODO: wide fanout
-    // TODO: Maximum number of packets to push in each gossip round.
+    // Min fraction of min stake of {this node, crds value owner} to retain
+    // in aggregate across ingress nodes when pruning received-cache.
+    pub gossip_prune_stake_threshold: f64,
+    // Minimum number of upserts of an origin before its ingress nodes can be
+    // pruned.
+    pub received_cache_min_num_upserts: usize,
+    // Maximum number of ingress nodes tracked for each origin.
+    pub received_cache_entry_capacity: usize,
+    // Number of duplicates before which a delivery is counted as timely
+    // towards the ingress node's score.
+    pub received_cache_num_dups_threshold: usize,
+    // Number of nodes in each push active-set entry.
+    pub push_active_set_size: usize,
+    // Number of stake buckets, each with its own push active-set entry.
+    pub num_push_active_set_entries: usize,
+    // Bloom filters of origins pruned by each node in the push active set.
+    pub push_active_set_bloom_false_rate: f64,
+    pub push_active_set_bloom_max_bits: usize,
+    // Maximum number of crds values to push in each gossip round, counting
+    // each destination separately; remaining keys are deferred to the next
+    // round.
     pub gossip_push_capacity: usize,
     pub packet_drop_rate: f64,
     pub num_crds: usize, // Number of crds values per node.
@@ -51,53 +155,180 @@ pub struct Config {
     pub refresh_rate: f64,
     pub num_threads: usize,
     pub run_duration: Duration,
+    // Seed for the simulation rng; worker threads use separate streams.
+    pub seed: u64,
+    // If true, runs a deterministic discrete-event simulation in virtual
+    // time instead of racing worker threads against the wall clock.
+    pub virtual_time: bool,
+    // Delay between consecutive gossip rounds of each node in virtual time.
+    pub gossip_interval: Duration,
     // Number of gossip rounds before collecting stats.
     pub warm_up_rounds: usize,
+    // Number of gossip rounds between pull requests; zero disables pull.
+    pub gossip_pull_rounds: usize,
+    // Number of peers sent a pull request in each pull round.
+    pub gossip_pull_fanout: usize,
+    // Crds values not updated within the timeout are purged from the table,
+    // and values with an older wallclock are rejected on arrival.
+    pub crds_timeout_staked: Duration,
+    pub crds_timeout_unstaked: Duration,
+    // Number of gossip rounds between purging crds tables; zero disables
+    // purging.
+    pub crds_purge_rounds: usize,
+    // Peers are admitted into the push active set only if they answered a
+    // ping within the ttl; zero disables ping/pong verification.
+    pub ping_cache_ttl: Duration,
+    // Minimum delay between pings to the same peer.
+    pub ping_cache_rate_limit_delay: Duration,
 }
 
-#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
 pub struct CrdsKey {
     origin: Pubkey,
     index: usize,
 }
 
-#[derive(Debug, Default)]
+// Gossip node as listed in json rpc or in a cluster snapshot file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct NodeInfo {
+    pub pubkey: Pubkey,
+    pub stake: u64,
+    pub shred_version: Option<u16>,
+}
+
+// Where the nodes of the simulated cluster are obtained from.
+pub enum ClusterSource {
+    // Snapshot file previously written by snapshot::write_snapshot, so that
+    // no json rpc access is needed.
+    Snapshot(PathBuf),
+    Synthetic {
+        num_nodes: usize,
+        distribution: StakeDistribution,
+        unstaked_fraction: f64,
+    },
+    JsonRpc(/*url:*/ String),
+}
+
+#[derive(Debug)]
 pub struct CrdsEntry {
     ordinal: u64,
     num_dups: u8,
+    // Time the value was created at its origin.
+    wallclock: Instant,
+    // Time the value first arrived at this node.
+    local_timestamp: Instant,
+    // Number of hops the value traveled from its origin to this node.
+    num_hops: u8,
 }
 
 #[derive(Clone)]
 pub enum Packet {
+    // Crds values batched up to PACKET_DATA_SIZE bytes.
     Push {
         from: Pubkey,
-        key: CrdsKey,
-        ordinal: u64,
+        values: Vec<(
+            CrdsKey,
+            /*ordinal:*/ u64,
+            /*wallclock:*/ Instant,
+            /*num_hops:*/ u8,
+        )>,
     },
     Prune {
         from: Pubkey,
         origins: Vec<Pubkey>,
     },
+    // Bloom filter of the crds values the requesting node already has, out
+    // of those with hash matching the mask in the first mask_bits bits.
+    PullRequest {
+        from: Pubkey,
+        filter: Bloom<Hash>,
+        mask: u64,
+        mask_bits: u32,
+    },
+    PullResponse {
+        from: Pubkey,
+        values: Vec<(
+            CrdsKey,
+            /*ordinal:*/ u64,
+            /*wallclock:*/ Instant,
+            /*num_hops:*/ u8,
+        )>,
+    },
+    Ping {
+        from: Pubkey,
+        token: u64,
+    },
+    // Echoes the token of the ping it responds to.
+    Pong {
+        from: Pubkey,
+        token: u64,
+    },
+}
+
+struct InFlight {
+    delivery_time: Instant,
+    packet: Arc<Packet>,
 }
 
 #[derive(Default)]
 pub struct ConsumeOutput {
-    keys: HashSet<CrdsKey>, // upserted keys
+    keys: HashSet<CrdsKey>,      // keys upserted by push messages
+    pull_keys: HashSet<CrdsKey>, // keys upserted by pull responses
     num_packets: usize,
+    num_push_values: usize, // crds values received in push messages
     num_prunes: usize,
+    num_pulls: usize, // pull requests and responses
     num_outdated: usize,
     num_duplicates: usize,
+    num_expired: usize, // values older than the crds timeout
 }
 
 enum UpsertError {
+    // Wallclock is older than the crds timeout of the origin.
+    Expired,
     Outdated,
     Duplicate(/*num_dups:*/ u8),
 }
 
-// TODO: should let nodes maintain their own view of the cluster?!
 // TODO: gossip loop 200ms delay!? listen vs gossip!?
 
 impl Node {
+    pub(crate) fn new(
+        clock: Instant,
+        pubkey: Pubkey,
+        stake: u64,
+    ) -> (Self, Sender<(Instant, Arc<Packet>)>) {
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let node = Node {
+            clock,
+            num_gossip_rounds: 0,
+            num_rotations: 0,
+            stake,
+            pubkey,
+            table: HashMap::default(),
+            restart_ordinal: 0,
+            active_set: PushActiveSet::default(),
+            received_cache: ReceivedCache::new(2 * CRDS_UNIQUE_PUBKEY_CAPACITY),
+            receiver,
+            inbox: BinaryHeap::default(),
+            propagation: PropagationStats::default(),
+            stats: NodeStats::default(),
+            deferred_keys: Vec::default(),
+            pull_requests: Vec::default(),
+            num_pruned_origins: HashMap::default(),
+            queueing_delay: Histogram::default(),
+            attacks: Vec::default(),
+            num_attack_packets: 0,
+            eclipse_victim: None,
+            entrypoints: Vec::default(),
+            ping_cache: None,
+            pending_pongs: Vec::default(),
+            prune_policy: Arc::new(StakeThreshold),
+            peer_sampler: Arc::new(StakeBucket(DEFAULT_BUCKET_EXPONENT)),
+        };
+        (node, sender)
+    }
+
     pub fn stake(&self) -> u64 {
         self.stake
     }
@@ -114,35 +345,121 @@ impl Node {
         self.num_gossip_rounds
     }
 
+    pub fn num_rotations(&self) -> usize {
+        self.num_rotations
+    }
+
+    pub fn propagation(&self) -> &PropagationStats {
+        &self.propagation
+    }
+
+    pub fn stats(&self) -> &NodeStats {
+        &self.stats
+    }
+
+    pub fn num_pruned_origins(&self) -> &HashMap<Pubkey, usize> {
+        &self.num_pruned_origins
+    }
+
+    pub fn queueing_delay(&self) -> &Histogram {
+        &self.queueing_delay
+    }
+
+    // Makes the node malicious, sending the given number of attack packets
+    // per gossip round on top of honest gossip.
+    pub fn add_attack(&mut self, attack: Attack, rate: usize) {
+        self.attacks.push((attack, rate));
+    }
+
+    pub fn is_adversary(&self) -> bool {
+        !self.attacks.is_empty()
+    }
+
+    pub fn num_attack_packets(&self) -> usize {
+        self.num_attack_packets
+    }
+
+    pub fn set_prune_policy(&mut self, prune_policy: Arc<dyn PrunePolicy>) {
+        self.prune_policy = prune_policy;
+    }
+
+    pub fn set_peer_sampler(&mut self, peer_sampler: Arc<dyn PeerSampler>) {
+        self.peer_sampler = peer_sampler;
+    }
+
+    pub fn set_entrypoints(&mut self, entrypoints: Vec<Pubkey>) {
+        self.entrypoints = entrypoints;
+    }
+
+    pub fn set_eclipse_victim(&mut self, victim: Pubkey) {
+        self.eclipse_victim = Some(victim);
+    }
+
+    // Returns nodes in the push active set, once for each stake-bucket entry
+    // they occupy a slot in.
+    pub fn active_set_nodes(&self) -> Vec<Pubkey> {
+        self.active_set.nodes().copied().collect()
+    }
+
     pub fn run_gossip<R: Rng>(
         &mut self,
         rng: &mut R,
+        now: Instant,
         config: &Config,
         stakes: &HashMap<Pubkey, u64>,
         router: &Router<Arc<Packet>>,
     ) -> Result<(), Error> {
-        let elapsed = self.clock.elapsed();
-        self.clock = Instant::now();
+        let timer = Instant::now();
+        let elapsed = now.saturating_duration_since(self.clock);
+        // Offline nodes skip gossip rounds, and come back online as if
+        // restarted.
+        if router.is_offline(now, &self.pubkey) {
+            self.clock = now;
+            return Ok(());
+        }
+        let restart = router.get_restart(self.clock, now, &self.pubkey);
+        self.clock = now;
+        if let Some(restart) = restart {
+            self.restart(restart);
+        }
         self.num_gossip_rounds += 1;
-        if self.num_gossip_rounds % config.rotate_active_set_rounds == 1 {
-            self.rotate_active_set(rng, config.gossip_push_fanout as usize, stakes);
+        if config.crds_purge_rounds != 0 && self.num_gossip_rounds % config.crds_purge_rounds == 0 {
+            self.purge(config, stakes);
+        }
+        if (self.num_gossip_rounds - 1) % config.rotate_active_set_rounds == 0 || restart.is_some()
+        {
+            self.rotate_active_set(rng, config, stakes, router)?;
+            self.stats.active_set_size = self.active_set.num_nodes();
         }
         // Drain the channel for incomming packets.
         // Insert new messages into the CRDS table.
         let ConsumeOutput {
             mut keys,
+            pull_keys,
             num_packets,
+            num_push_values,
             num_prunes,
+            num_pulls,
             num_outdated,
             num_duplicates,
-        } = self.consume_packets(stakes);
+            num_expired,
+        } = self.consume_packets(self.clock, config, stakes);
         // Send prune messages for upserted origins.
         {
-            let origins = keys.iter().map(|key| key.origin);
+            let origins = keys.iter().map(|key| key.origin).sorted_unstable();
             self.send_prunes(rng, origins, config, stakes, router)?;
         }
+        self.send_pull_responses(rng, config, router)?;
+        self.send_pongs(rng, config, router)?;
+        if config.gossip_pull_rounds != 0 && self.num_gossip_rounds % config.gossip_pull_rounds == 0
+        {
+            self.send_pull_requests(rng, config, stakes, router)?;
+        }
+        // Values obtained through pull requests are pushed as well.
+        keys.extend(pull_keys);
         // Refresh own gossip entries!
         keys.extend(self.refresh_entries(rng, config));
+        keys.extend(std::mem::take(&mut self.deferred_keys));
         // Sort updated keys by origin's stake.
         let keys: Vec<_> = keys
             .into_iter()
@@ -150,17 +467,29 @@ impl Node {
                 let stake = stakes.get(&key.origin).copied().unwrap_or_default();
                 (stake, key)
             })
-            .sorted_unstable_by_key(|(stake, _)| Reverse(*stake))
+            .sorted_unstable_by_key(|&(stake, key)| (Reverse(stake), key))
             .map(|(_stake, key)| key)
             .collect();
         let num_keys = keys.len();
-        // Push/fanout overwritten keys to other nodes.
-        for key in keys {
-            let packet = Arc::new(Packet::Push {
-                from: self.pubkey,
+        // Push/fanout overwritten keys to other nodes, up to the push budget.
+        // Each key is pushed to its full fanout; the first key which does not
+        // fit in the remaining budget is deferred along with the rest, unless
+        // the budget is smaller than a single fanout. Values destined to each
+        // node are batched into packets of at most PACKET_DATA_SIZE bytes.
+        let mut keys = keys.into_iter();
+        let mut num_pushes = 0;
+        let mut batches = HashMap::<Pubkey, (/*size:*/ usize, Vec<_>)>::new();
+        while num_pushes < config.gossip_push_capacity {
+            let Some(key) = keys.next() else {
+                break;
+            };
+            let entry = &self.table[&key];
+            let value = (
                 key,
-                ordinal: self.table[&key].ordinal,
-            });
+                entry.ordinal,
+                entry.wallclock,
+                entry.num_hops.saturating_add(1),
+            );
             let gossip_push_fanout = if key.origin == self.pubkey {
                 config.gossip_push_wide_fanout
             } else {
@@ -168,43 +497,196 @@ impl Node {
             };
             let gossip_push_fanout =
                 gossip_push_fanout as usize + rng.gen_bool(gossip_push_fanout % 1.0) as usize;
-            for node in self
+            let mut nodes: Vec<Pubkey> = self
                 .active_set
                 .get_nodes(&self.pubkey, &key.origin, |_| false, stakes)
+                .filter(|&&node| Some(node) != self.eclipse_victim)
                 .take(gossip_push_fanout)
-            {
-                assert_ne!(node, &self.pubkey);
-                router.send(rng, node, packet.clone())?;
+                .copied()
+                .collect();
+            if num_pushes + nodes.len() > config.gossip_push_capacity {
+                if num_pushes != 0 {
+                    self.deferred_keys.push(key);
+                    break;
+                }
+                nodes.truncate(config.gossip_push_capacity);
+            }
+            let value_size = key.value_size();
+            for node in nodes {
+                assert_ne!(node, self.pubkey);
+                let (size, values) = batches
+                    .entry(node)
+                    .or_insert_with(|| (PUSH_MESSAGE_HEADER_SIZE, Vec::default()));
+                if !values.is_empty() && *size + value_size > PACKET_DATA_SIZE {
+                    let values = std::mem::take(values);
+                    *size = PUSH_MESSAGE_HEADER_SIZE;
+                    self.send_push(rng, config, router, node, values)?;
+                }
+                *size += value_size;
+                values.push(value);
+                num_pushes += 1;
             }
         }
+        for (node, (_, values)) in batches
+            .into_iter()
+            .sorted_unstable_by_key(|(node, _)| *node)
+        {
+            self.send_push(rng, config, router, node, values)?;
+        }
+        // Keys exceeding the push budget are pushed in the next round.
+        self.deferred_keys.extend(keys);
+        self.send_attacks(rng, config, stakes, router)?;
+        self.stats.table_size = self.table.len();
         let get_ratio = |num| {
-            if num_packets == num_prunes {
+            if num_push_values == 0 {
                 0.0
             } else {
-                num as f64 * 100.0 / (num_packets - num_prunes) as f64
+                num as f64 * 100.0 / num_push_values as f64
             }
         };
         if rng.gen_ratio(1, 1000) {
             trace!(
-                "{}, {:?}: {}ms, round: {}, packets: {}, prunes: {}, \
-                outdated: {}, {:.0}%, duplicates: {}, {:.0}%, keys: {}, {}ms",
+                "{}, {:?}: {}ms, round: {}, packets: {}, prunes: {}, pulls: {}, \
+                outdated: {}, {:.0}%, duplicates: {}, {:.0}%, expired: {}, \
+                keys: {}, deferred: {}, {}ms",
                 &format!("{}", self.pubkey)[..8],
                 std::thread::current().id(),
                 elapsed.as_millis(),
                 self.num_gossip_rounds,
                 num_packets,
                 num_prunes,
+                num_pulls,
                 num_outdated,
                 get_ratio(num_outdated),
                 num_duplicates,
                 get_ratio(num_duplicates),
+                num_expired,
                 num_keys,
-                self.clock.elapsed().as_millis(),
+                self.deferred_keys.len(),
+                timer.elapsed().as_millis(),
             );
         }
         Ok(())
     }
 
+    fn send_push<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        router: &Router<Arc<Packet>>,
+        node: Pubkey,
+        values: Vec<(CrdsKey, u64, Instant, u8)>,
+    ) -> Result<(), Error> {
+        let packet = Arc::new(Packet::Push {
+            from: self.pubkey,
+            values,
+        });
+        let delivery = router.send(rng, self.clock, &self.pubkey, &node, packet.clone())?;
+        self.record_delivery(config, &packet, delivery);
+        if self.collect_stats(config) {
+            self.stats.num_pushes_sent += 1;
+        }
+        Ok(())
+    }
+
+    // Sends attack packets of each of the node's malicious behaviors to
+    // random gossip peers.
+    fn send_attacks<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        stakes: &HashMap<Pubkey, u64>,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        if self.attacks.is_empty() {
+            return Ok(());
+        }
+        let peers = self.get_gossip_peers(stakes);
+        let keys: Vec<CrdsKey> = self
+            .table
+            .keys()
+            .filter(|key| key.origin != self.pubkey)
+            .copied()
+            .sorted_unstable()
+            .collect();
+        if peers.len() < 2 || keys.is_empty() {
+            return Ok(());
+        }
+        for (attack, rate) in self.attacks.clone() {
+            for _ in 0..rate {
+                let node = *peers.choose(rng).unwrap();
+                // Honest node other than the destination, to forge from field.
+                let get_forged = |rng: &mut R| {
+                    *peers
+                        .iter()
+                        .filter(|&&peer| peer != node)
+                        .choose(rng)
+                        .unwrap()
+                };
+                let packet = match attack {
+                    Attack::Spam | Attack::Replay => Packet::Push {
+                        from: self.pubkey,
+                        values: self.make_attack_values(rng, attack, &keys),
+                    },
+                    Attack::Spoof => Packet::Push {
+                        from: get_forged(rng),
+                        values: self.make_attack_values(rng, attack, &keys),
+                    },
+                    Attack::Prune => {
+                        let from = get_forged(rng);
+                        let origins = peers
+                            .iter()
+                            .filter(|&&origin| origin != node && origin != from)
+                            .copied()
+                            .choose_multiple(rng, NUM_BOGUS_PRUNE_ORIGINS);
+                        Packet::Prune { from, origins }
+                    }
+                };
+                let packet = Arc::new(packet);
+                let delivery = router.send(rng, self.clock, &self.pubkey, &node, packet.clone())?;
+                self.record_delivery(config, &packet, delivery);
+                if self.collect_stats(config) {
+                    self.num_attack_packets += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Returns crds values of random keys, up to PACKET_DATA_SIZE bytes:
+    //   * Spam: stale values with a random older ordinal.
+    //   * Replay: values which were just overwritten, with the previous
+    //     ordinal.
+    //   * Spoof: current values as in the node's table.
+    fn make_attack_values<R: Rng>(
+        &self,
+        rng: &mut R,
+        attack: Attack,
+        keys: &[CrdsKey],
+    ) -> Vec<(CrdsKey, u64, Instant, u8)> {
+        let mut size = PUSH_MESSAGE_HEADER_SIZE;
+        let mut values = Vec::default();
+        loop {
+            let key = *keys.choose(rng).unwrap();
+            size += key.value_size();
+            if !values.is_empty() && size > PACKET_DATA_SIZE {
+                return values;
+            }
+            let entry = &self.table[&key];
+            let ordinal = match attack {
+                Attack::Spam => rng.gen_range(0, entry.ordinal.max(1)),
+                Attack::Replay => entry.ordinal.saturating_sub(1),
+                Attack::Spoof | Attack::Prune => entry.ordinal,
+            };
+            values.push((
+                key,
+                ordinal,
+                entry.wallclock,
+                entry.num_hops.saturating_add(1),
+            ));
+        }
+    }
+
     fn send_prunes<R: Rng>(
         &mut self,
         rng: &mut R,
@@ -218,21 +700,175 @@ impl Node {
             .flat_map(|origin| {
                 self.received_cache
                     .prune(
+                        rng,
                         &self.pubkey,
                         origin,
-                        CRDS_GOSSIP_PRUNE_STAKE_THRESHOLD_PCT,
+                        &*self.prune_policy,
+                        config.received_cache_min_num_upserts,
+                        config.gossip_prune_stake_threshold,
                         config.gossip_prune_min_ingress_nodes,
                         stakes,
                     )
                     .zip(repeat(origin))
             })
             .into_group_map();
-        for (node, origins) in prunes {
-            let packet = Packet::Prune {
+        for (node, origins) in prunes.into_iter().sorted_unstable_by_key(|(node, _)| *node) {
+            if self.collect_stats(config) {
+                self.stats.num_prunes_sent += 1;
+                *self.num_pruned_origins.entry(node).or_default() += origins.len();
+            }
+            let packet = Arc::new(Packet::Prune {
                 from: self.pubkey,
                 origins,
+            });
+            let delivery = router.send(rng, self.clock, &self.pubkey, &node, packet.clone())?;
+            self.record_delivery(config, &packet, delivery);
+        }
+        Ok(())
+    }
+
+    // Sends a bloom filter of the crds values in the table to peers sampled
+    // by stake, so that they respond with the values missing in this node.
+    fn send_pull_requests<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        stakes: &HashMap<Pubkey, u64>,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        let nodes = self.get_gossip_peers(stakes);
+        if nodes.is_empty() {
+            return Ok(());
+        }
+        // Similar to push active set, peers are weighted by the peer sampler
+        // for the stake bucket of this node, i.e. by
+        //     min stake of {this node, gossip peer}
+        // under the default sampler.
+        let k = get_stake_bucket(stakes.get(&self.pubkey), config.num_push_active_set_entries);
+        let peer_stakes: Vec<u64> = nodes
+            .iter()
+            .map(|node| stakes.get(node).copied().unwrap_or_default())
+            .collect();
+        let weights = get_weights(
+            &*self.peer_sampler,
+            k,
+            config.num_push_active_set_entries,
+            &peer_stakes,
+        );
+        let filters = make_pull_request_filters(
+            rng,
+            self.table.len(),
+            self.table
+                .iter()
+                .map(|(key, entry)| get_value_hash(key, entry.ordinal)),
+        );
+        let peers: Vec<Pubkey> = WeightedShuffle::new("pull-request-peers", &weights)
+            .shuffle(rng)
+            .map(|k| nodes[k])
+            .take(config.gossip_pull_fanout)
+            .collect();
+        if peers.is_empty() {
+            return Ok(());
+        }
+        // Filters are spread across the sampled peers.
+        let num_filters = filters.len();
+        for ((filter, mask, mask_bits), node) in filters.into_iter().zip(peers.iter().cycle()) {
+            let packet = Arc::new(Packet::PullRequest {
+                from: self.pubkey,
+                filter,
+                mask,
+                mask_bits,
+            });
+            let delivery = router.send(rng, self.clock, &self.pubkey, node, packet.clone())?;
+            self.record_delivery(config, &packet, delivery);
+        }
+        if self.collect_stats(config) {
+            self.stats.num_pull_requests_sent += num_filters;
+        }
+        Ok(())
+    }
+
+    // Answers pings received since the last round with pongs echoing their
+    // tokens.
+    fn send_pongs<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        for (node, token) in std::mem::take(&mut self.pending_pongs) {
+            let packet = Arc::new(Packet::Pong {
+                from: self.pubkey,
+                token,
+            });
+            let delivery = router.send(rng, self.clock, &self.pubkey, &node, packet.clone())?;
+            self.record_delivery(config, &packet, delivery);
+        }
+        Ok(())
+    }
+
+    // Responds to pending pull requests with a random sample of crds values
+    // missing from the requesting node's bloom filter.
+    fn send_pull_responses<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        for request in std::mem::take(&mut self.pull_requests) {
+            let Packet::PullRequest {
+                from,
+                ref filter,
+                mask,
+                mask_bits,
+            } = *request
+            else {
+                continue;
             };
-            router.send(rng, &node, Arc::new(packet))?;
+            let keys: Vec<_> = self
+                .table
+                .iter()
+                .filter(|(key, entry)| {
+                    let hash = get_value_hash(key, entry.ordinal);
+                    test_mask(&hash, mask, mask_bits) && !filter.contains(&hash)
+                })
+                .map(|(key, _)| *key)
+                .sorted_unstable()
+                .collect();
+            if keys.is_empty() {
+                continue;
+            }
+            let values = keys
+                .choose_multiple(rng, CRDS_GOSSIP_PULL_RESPONSE_CAPACITY)
+                .map(|key| {
+                    let entry = &self.table[key];
+                    let num_hops = entry.num_hops.saturating_add(1);
+                    (*key, entry.ordinal, entry.wallclock, num_hops)
+                });
+            // As with push messages, values are batched into packets of at
+            // most PACKET_DATA_SIZE bytes.
+            let mut batches = vec![(PUSH_MESSAGE_HEADER_SIZE, Vec::default())];
+            for value in values {
+                let value_size = value.0.value_size();
+                let (size, values) = batches.last_mut().unwrap();
+                if !values.is_empty() && *size + value_size > PACKET_DATA_SIZE {
+                    batches.push((PUSH_MESSAGE_HEADER_SIZE + value_size, vec![value]));
+                } else {
+                    *size += value_size;
+                    values.push(value);
+                }
+            }
+            for (_, values) in batches {
+                let packet = Arc::new(Packet::PullResponse {
+                    from: self.pubkey,
+                    values,
+                });
+                let delivery = router.send(rng, self.clock, &self.pubkey, &from, packet.clone())?;
+                self.record_delivery(config, &packet, delivery);
+                if self.collect_stats(config) {
+                    self.stats.num_pull_responses_sent += 1;
+                }
+            }
         }
         Ok(())
     }
@@ -252,40 +888,125 @@ impl Node {
                     origin: self.pubkey,
                     index,
                 };
-                self.table.entry(key).or_default().ordinal += 1;
+                let now = self.clock;
+                let ordinal = self.restart_ordinal;
+                let entry = self.table.entry(key).or_insert_with(|| CrdsEntry {
+                    ordinal,
+                    num_dups: 0u8,
+                    wallclock: now,
+                    local_timestamp: now,
+                    num_hops: 0u8,
+                });
+                entry.ordinal += 1;
+                entry.wallclock = now;
+                entry.local_timestamp = now;
                 key
             })
     }
 
     /// Drains the channel for incoming packets and updates crds table.
-    pub fn consume_packets(&mut self, stakes: &HashMap<Pubkey, u64>) -> ConsumeOutput {
-        let packets: Vec<_> = self.receiver.try_iter().collect();
+    /// Only packets which are due by `now` are delivered; the rest are
+    /// buffered until a later call. Stats are not collected during the
+    /// node's first config.warm_up_rounds gossip rounds.
+    pub fn consume_packets(
+        &mut self,
+        now: Instant,
+        config: &Config,
+        stakes: &HashMap<Pubkey, u64>,
+    ) -> ConsumeOutput {
+        let collect_stats = self.collect_stats(config);
+        self.inbox.extend(
+            self.receiver
+                .try_iter()
+                .map(|(delivery_time, packet)| InFlight {
+                    delivery_time,
+                    packet,
+                })
+                .map(Reverse),
+        );
+        let packets: Vec<_> = std::iter::from_fn(|| match self.inbox.peek() {
+            Some(Reverse(entry)) if entry.delivery_time <= now => self.inbox.pop(),
+            _ => None,
+        })
+        .map(|Reverse(entry)| (entry.delivery_time, entry.packet))
+        .collect();
         // Insert new messages into the CRDS table.
         let mut out = ConsumeOutput {
             num_packets: packets.len(),
             ..ConsumeOutput::default()
         };
-        for packet in packets {
+        if collect_stats {
+            self.stats.num_bytes_received += packets
+                .iter()
+                .map(|(_, packet)| packet.wire_size())
+                .sum::<usize>();
+        }
+        for (delivery_time, packet) in packets {
+            if let Some(victim) = self.eclipse_victim {
+                if matches!(*packet, Packet::Push { from, .. }
+                    | Packet::PullRequest { from, .. } if from == victim)
+                {
+                    continue;
+                }
+            }
             match *packet {
-                Packet::Push { from, key, ordinal } => {
-                    match self.upsert(key, ordinal) {
-                        Ok(()) => {
-                            self.received_cache
-                                .record(key.origin, from, /*num_dups:*/ 0);
-                            out.keys.insert(key);
-                        }
-                        Err(UpsertError::Outdated) => {
-                            self.received_cache.record(
-                                key.origin,
-                                from,
-                                usize::MAX, // num_dups
-                            );
-                            out.num_outdated += 1;
-                        }
-                        Err(UpsertError::Duplicate(num_dups)) => {
-                            self.received_cache
-                                .record(key.origin, from, usize::from(num_dups));
-                            out.num_duplicates += 1;
+                Packet::Push { from, ref values } => {
+                    out.num_push_values += values.len();
+                    for &(key, ordinal, wallclock, num_hops) in values {
+                        let entry = CrdsEntry {
+                            ordinal,
+                            num_dups: 0u8,
+                            wallclock,
+                            local_timestamp: delivery_time,
+                            num_hops,
+                        };
+                        match self.upsert(key, entry, config, stakes) {
+                            Ok(()) => {
+                                if collect_stats {
+                                    self.propagation.record(
+                                        delivery_time.saturating_duration_since(wallclock),
+                                        num_hops,
+                                    );
+                                }
+                                self.received_cache.record(
+                                    key.origin,
+                                    from,
+                                    0, // num_dups
+                                    config.received_cache_num_dups_threshold,
+                                    config.received_cache_entry_capacity,
+                                );
+                                out.keys.insert(key);
+                            }
+                            Err(UpsertError::Expired) => {
+                                self.received_cache.record(
+                                    key.origin,
+                                    from,
+                                    usize::MAX, // num_dups
+                                    config.received_cache_num_dups_threshold,
+                                    config.received_cache_entry_capacity,
+                                );
+                                out.num_expired += 1;
+                            }
+                            Err(UpsertError::Outdated) => {
+                                self.received_cache.record(
+                                    key.origin,
+                                    from,
+                                    usize::MAX, // num_dups
+                                    config.received_cache_num_dups_threshold,
+                                    config.received_cache_entry_capacity,
+                                );
+                                out.num_outdated += 1;
+                            }
+                            Err(UpsertError::Duplicate(num_dups)) => {
+                                self.received_cache.record(
+                                    key.origin,
+                                    from,
+                                    usize::from(num_dups),
+                                    config.received_cache_num_dups_threshold,
+                                    config.received_cache_entry_capacity,
+                                );
+                                out.num_duplicates += 1;
+                            }
                         }
                     }
                 }
@@ -296,21 +1017,82 @@ impl Node {
                     out.num_prunes += 1;
                     self.active_set.prune(&self.pubkey, from, origins, stakes);
                 }
+                Packet::PullRequest { .. } => {
+                    out.num_pulls += 1;
+                    self.pull_requests.push(packet);
+                }
+                Packet::Ping { from, token } => self.pending_pongs.push((from, token)),
+                Packet::Pong { ref from, token } => {
+                    if let Some(ping_cache) = &mut self.ping_cache {
+                        ping_cache.add(delivery_time, from, token);
+                    }
+                }
+                Packet::PullResponse { ref values, .. } => {
+                    out.num_pulls += 1;
+                    for &(key, ordinal, wallclock, num_hops) in values {
+                        let entry = CrdsEntry {
+                            ordinal,
+                            num_dups: 0u8,
+                            wallclock,
+                            local_timestamp: delivery_time,
+                            num_hops,
+                        };
+                        // Pull responses do not count towards received-cache
+                        // scores and so do not trigger prunes.
+                        match self.upsert(key, entry, config, stakes) {
+                            Ok(()) => {
+                                if collect_stats {
+                                    self.propagation.record(
+                                        delivery_time.saturating_duration_since(wallclock),
+                                        num_hops,
+                                    );
+                                }
+                                out.pull_keys.insert(key);
+                            }
+                            Err(UpsertError::Expired) => out.num_expired += 1,
+                            Err(UpsertError::Outdated | UpsertError::Duplicate(_)) => (),
+                        }
+                    }
+                }
             }
         }
+        if collect_stats {
+            self.stats.num_packets += out.num_packets;
+            self.stats.num_push_values += out.num_push_values;
+            self.stats.num_prunes += out.num_prunes;
+            self.stats.num_pulls += out.num_pulls;
+            self.stats.num_push_upserts += out.keys.len();
+            self.stats.num_pull_upserts += out.pull_keys.len();
+            self.stats.num_outdated += out.num_outdated;
+            self.stats.num_duplicates += out.num_duplicates;
+            self.stats.num_expired += out.num_expired;
+        }
         out
     }
 
-    fn upsert(&mut self, key: CrdsKey, ordinal: u64) -> Result<(), UpsertError> {
+    fn upsert(
+        &mut self,
+        key: CrdsKey,
+        value: CrdsEntry,
+        config: &Config,
+        stakes: &HashMap<Pubkey, u64>,
+    ) -> Result<(), UpsertError> {
+        // Purged values have a wallclock older than the timeout, so this also
+        // prevents them from being re-inserted with the same ordinal.
+        let timeout = self.get_crds_timeout(&key.origin, config, stakes);
+        if value
+            .local_timestamp
+            .saturating_duration_since(value.wallclock)
+            > timeout
+        {
+            return Err(UpsertError::Expired);
+        }
         match self.table.entry(key) {
             Entry::Occupied(mut entry) => {
                 let entry = entry.get_mut();
-                match entry.ordinal.cmp(&ordinal) {
+                match entry.ordinal.cmp(&value.ordinal) {
                     Ordering::Less => {
-                        *entry = CrdsEntry {
-                            ordinal,
-                            num_dups: 0u8,
-                        };
+                        *entry = value;
                         Ok(())
                     }
                     Ordering::Equal => {
@@ -321,34 +1103,193 @@ impl Node {
                 }
             }
             Entry::Vacant(entry) => {
-                entry.insert(CrdsEntry {
-                    ordinal,
-                    num_dups: 0u8,
-                });
+                entry.insert(value);
                 Ok(())
             }
         }
     }
 
-    fn rotate_active_set<R: Rng>(
-        &mut self,
-        rng: &mut R,
-        gossip_push_fanout: usize,
-        stakes: &HashMap<Pubkey, u64>,
-    ) {
-        // Gossip nodes to be sampled for each push active set.
-        // TODO: this should only be a set of entrypoints not all staked nodes.
-        let nodes: Vec<_> = stakes
+    // Removes crds values which have not been updated within the timeout of
+    // their origin, as in Crds::find_old_labels. Own values are never purged.
+    fn purge(&mut self, config: &Config, stakes: &HashMap<Pubkey, u64>) {
+        let now = self.clock;
+        let num_entries = self.table.len();
+        let timeouts: HashMap<Pubkey, Duration> = self
+            .table
             .keys()
+            .map(|key| key.origin)
+            .unique()
+            .map(|origin| (origin, self.get_crds_timeout(&origin, config, stakes)))
+            .collect();
+        self.table.retain(|key, entry| {
+            now.saturating_duration_since(entry.local_timestamp) <= timeouts[&key.origin]
+        });
+        // Purged keys cannot be pushed anymore.
+        let table = &self.table;
+        self.deferred_keys.retain(|key| table.contains_key(key));
+        if self.collect_stats(config) {
+            self.stats.num_purged += num_entries - self.table.len();
+        }
+    }
+
+    fn get_crds_timeout(
+        &self,
+        origin: &Pubkey,
+        config: &Config,
+        stakes: &HashMap<Pubkey, u64>,
+    ) -> Duration {
+        if origin == &self.pubkey {
+            Duration::MAX
+        } else if stakes.get(origin).copied().unwrap_or_default() > 0 {
+            config.crds_timeout_staked
+        } else {
+            config.crds_timeout_unstaked
+        }
+    }
+
+    // Returns gossip nodes known to this node, excluding itself. Without
+    // entrypoints the node knows all staked nodes; otherwise it only knows
+    // its entrypoints and the origins of crds values in its table.
+    fn get_gossip_peers(&self, stakes: &HashMap<Pubkey, u64>) -> Vec<Pubkey> {
+        let staked_nodes = self
+            .entrypoints
+            .is_empty()
+            .then(|| stakes.keys())
+            .into_iter()
+            .flatten();
+        self.entrypoints
+            .iter()
+            .chain(staked_nodes)
             .copied()
             .chain(self.table.keys().map(|key| key.origin))
             .filter(|pubkey| pubkey != &self.pubkey)
             .collect::<HashSet<_>>()
             .into_iter()
+            .sorted_unstable()
+            .collect()
+    }
+
+    // Resets the node state as if the validator restarted at the given time.
+    fn restart(&mut self, time: Instant) {
+        self.restart_ordinal = self
+            .table
+            .iter()
+            .filter(|(key, _)| key.origin == self.pubkey)
+            .map(|(_, entry)| entry.ordinal)
+            .max()
+            .unwrap_or_default()
+            .max(self.restart_ordinal);
+        self.table.clear();
+        self.active_set = PushActiveSet::default();
+        self.received_cache = ReceivedCache::new(2 * CRDS_UNIQUE_PUBKEY_CAPACITY);
+        self.deferred_keys.clear();
+        self.pull_requests.clear();
+        self.ping_cache = None;
+        self.pending_pongs.clear();
+        // Discard packets which arrived while the node was offline.
+        let inbox = std::mem::take(&mut self.inbox);
+        self.inbox = inbox
+            .into_iter()
+            .chain(
+                self.receiver
+                    .try_iter()
+                    .map(|(delivery_time, packet)| InFlight {
+                        delivery_time,
+                        packet,
+                    })
+                    .map(Reverse),
+            )
+            .filter(|Reverse(entry)| entry.delivery_time >= time)
             .collect();
+    }
+
+    // Stats are not collected during the node's warm-up rounds.
+    fn collect_stats(&self, config: &Config) -> bool {
+        self.num_gossip_rounds > config.warm_up_rounds
+    }
+
+    fn record_delivery(&mut self, config: &Config, packet: &Packet, delivery: Delivery) {
+        if !self.collect_stats(config) {
+            return;
+        }
+        if delivery != Delivery::EgressQueueFull {
+            self.stats.num_bytes_sent += packet.wire_size();
+        }
+        match delivery {
+            Delivery::Delivered { queueing_delay } => self.queueing_delay.add(queueing_delay),
+            Delivery::Dropped => {
+                if matches!(packet, Packet::Push { .. }) {
+                    self.stats.num_push_drops += 1;
+                }
+            }
+            Delivery::EgressQueueFull => self.stats.num_egress_drops += 1,
+            Delivery::IngressQueueFull => self.stats.num_ingress_drops += 1,
+        }
+    }
+
+    fn rotate_active_set<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        stakes: &HashMap<Pubkey, u64>,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        // Gossip nodes to be sampled for each push active set.
+        let nodes = self.get_gossip_peers(stakes);
         let cluster_size = nodes.len();
-        self.active_set
-            .rotate(rng, gossip_push_fanout * 3, cluster_size, &nodes, stakes);
+        // Only peers which answered a ping are admitted; the rest are pinged
+        // so that they may be admitted in later rotations.
+        let nodes = if config.ping_cache_ttl.is_zero() {
+            nodes
+        } else {
+            let ping_cache = self.ping_cache.get_or_insert_with(|| {
+                PingCache::new(config.ping_cache_ttl, config.ping_cache_rate_limit_delay)
+            });
+            let mut pings = Vec::default();
+            let nodes: Vec<_> = nodes
+                .into_iter()
+                .filter(|node| {
+                    let (verified, token) = ping_cache.check(rng, self.clock, node);
+                    pings.extend(token.map(|token| (*node, token)));
+                    verified
+                })
+                .collect();
+            for (node, token) in pings {
+                let packet = Arc::new(Packet::Ping {
+                    from: self.pubkey,
+                    token,
+                });
+                let delivery = router.send(rng, self.clock, &self.pubkey, &node, packet.clone())?;
+                self.record_delivery(config, &packet, delivery);
+                if self.collect_stats(config) {
+                    self.stats.num_pings_sent += 1;
+                }
+            }
+            nodes
+        };
+        self.num_rotations += 1;
+        self.active_set.rotate(
+            rng,
+            config.push_active_set_size,
+            config.num_push_active_set_entries,
+            cluster_size,
+            &nodes,
+            stakes,
+            &*self.peer_sampler,
+            config.push_active_set_bloom_false_rate,
+            config.push_active_set_bloom_max_bits,
+        );
+        Ok(())
+    }
+}
+
+impl CrdsKey {
+    pub fn origin(&self) -> Pubkey {
+        self.origin
+    }
+
+    fn value_size(&self) -> usize {
+        get_crds_value_size(self.index)
     }
 }
 
@@ -356,12 +1297,215 @@ impl CrdsEntry {
     pub fn ordinal(&self) -> u64 {
         self.ordinal
     }
+
+    pub fn wallclock(&self) -> Instant {
+        self.wallclock
+    }
+
+    pub fn local_timestamp(&self) -> Instant {
+        self.local_timestamp
+    }
+
+    pub fn num_hops(&self) -> u8 {
+        self.num_hops
+    }
+}
+
+// Returns the bincode encoded size of the crds value, assigning crds key
+// indices to the kinds of values a validator publishes: one each of
+// contact-info, node-instance, version, lowest-slot and snapshot-hashes, then
+// 32 votes, and epoch-slots for the remaining indices.
+fn get_crds_value_size(index: usize) -> usize {
+    let data_size = match index {
+        // LegacyContactInfo { id, 10 socket addresses, wallclock, shred_version }
+        0 => PUBKEY_SIZE + 10 * SOCKET_ADDR_SIZE + 8 + 2,
+        // NodeInstance { from, wallclock, timestamp, token }
+        1 => PUBKEY_SIZE + 8 + 8 + 8,
+        // Version { from, wallclock, major, minor, patch, commit, feature_set }
+        2 => PUBKEY_SIZE + 8 + 2 + 2 + 2 + 5 + 4,
+        // LowestSlot { index, from, root, lowest, slots, stash, wallclock }
+        3 => 1 + PUBKEY_SIZE + 8 + 8 + LEN_SIZE + LEN_SIZE + 8,
+        // SnapshotHashes { from, full, incremental, wallclock } with 8
+        // incremental (slot, hash) entries.
+        4 => PUBKEY_SIZE + 40 + LEN_SIZE + 8 * 40 + 8,
+        // Vote { index, from, transaction, wallclock }
+        5..=36 => 1 + PUBKEY_SIZE + VOTE_TRANSACTION_SIZE + 8,
+        // EpochSlots
+        _ => MAX_CRDS_OBJECT_SIZE - SIGNATURE_SIZE - TAG_SIZE,
+    };
+    // CrdsValue { signature, data }
+    SIGNATURE_SIZE + TAG_SIZE + data_size
+}
+
+// Returns the bincode encoded size of a pull request with a bloom filter of
+// the given number of keys and bits.
+fn get_pull_request_size(num_keys: usize, num_bits: usize) -> usize {
+    let num_words = (num_bits + 63) / 64;
+    // Bloom { keys, bits: BitVec { Option<Box<[u64]>>, len }, num_bits_set }
+    let bloom_size = LEN_SIZE + 8 * num_keys + 1 + LEN_SIZE + 8 * num_words + 8 + 8;
+    TAG_SIZE + bloom_size + 8 + 4 + get_crds_value_size(/*index:*/ 0)
+}
+
+// Splits the value hashes into bloom filters by the first mask_bits bits of
+// the hash, so that each pull request fits in PACKET_DATA_SIZE bytes, as in
+// solana-gossip CrdsFilterSet. Returns (filter, mask, mask_bits) tuples.
+fn make_pull_request_filters<R: Rng>(
+    rng: &mut R,
+    num_items: usize,
+    hashes: impl IntoIterator<Item = Hash>,
+) -> Vec<(Bloom<Hash>, /*mask:*/ u64, /*mask_bits:*/ u32)> {
+    let max_bits = (PACKET_DATA_SIZE - get_pull_request_size(CRDS_FILTER_NUM_KEYS as usize, 0)) * 8;
+    // https://hur.st/bloomfilter/
+    let max_items = {
+        let (m, p, k) = (
+            max_bits as f64,
+            CRDS_GOSSIP_PULL_FALSE_RATE,
+            CRDS_FILTER_NUM_KEYS,
+        );
+        (m / (-k / (1f64 - (p.ln() / k).exp()).ln())).ceil()
+    };
+    let mask_bits = (num_items as f64 / max_items).log2().ceil().max(0.0) as u32;
+    let mut filters: Vec<_> = (0..1u64 << mask_bits)
+        .map(|index| {
+            let filter = make_bloom_filter(
+                rng,
+                max_items as usize,
+                CRDS_GOSSIP_PULL_FALSE_RATE,
+                max_bits,
+            );
+            let mask = index.checked_shl(64 - mask_bits).unwrap_or_default() | (!0u64 >> mask_bits);
+            (filter, mask, mask_bits)
+        })
+        .collect();
+    for hash in hashes {
+        let index = get_hash_prefix(&hash)
+            .checked_shr(64 - mask_bits)
+            .unwrap_or_default();
+        filters[index as usize].0.add(&hash);
+    }
+    filters
+}
+
+// Returns true if the first mask_bits bits of the hash match the mask.
+fn test_mask(hash: &Hash, mask: u64, mask_bits: u32) -> bool {
+    get_hash_prefix(hash) | (!0u64 >> mask_bits) == mask
+}
+
+fn get_hash_prefix(hash: &Hash) -> u64 {
+    u64::from_le_bytes(hash.as_ref()[..8].try_into().unwrap())
+}
+
+// Identifies a crds value in pull request bloom filters.
+fn get_value_hash(key: &CrdsKey, ordinal: u64) -> Hash {
+    hashv(&[
+        key.origin.as_ref(),
+        &key.index.to_le_bytes(),
+        &ordinal.to_le_bytes(),
+    ])
+}
+
+// Bincode encoded size of the corresponding gossip protocol message.
+impl WireSize for Packet {
+    fn wire_size(&self) -> usize {
+        match self {
+            // Protocol::PushMessage(from, values)
+            Self::Push { values, .. } => {
+                let values_size: usize = values.iter().map(|(key, ..)| key.value_size()).sum();
+                PUSH_MESSAGE_HEADER_SIZE + values_size
+            }
+            // Protocol::PruneMessage(from, PruneData {
+            //     pubkey, prunes, signature, destination, wallclock,
+            // })
+            Self::Prune { origins, .. } => {
+                TAG_SIZE
+                    + PUBKEY_SIZE
+                    + PUBKEY_SIZE
+                    + LEN_SIZE
+                    + PUBKEY_SIZE * origins.len()
+                    + SIGNATURE_SIZE
+                    + PUBKEY_SIZE
+                    + 8
+            }
+            // Protocol::PullRequest(CrdsFilter { filter, mask, mask_bits }, caller)
+            // where the caller is its contact-info.
+            Self::PullRequest { filter, .. } => {
+                get_pull_request_size(filter.keys.len(), filter.bits.len() as usize)
+            }
+            // Protocol::PullResponse(from, values)
+            Self::PullResponse { values, .. } => {
+                let values_size: usize = values.iter().map(|(key, ..)| key.value_size()).sum();
+                PUSH_MESSAGE_HEADER_SIZE + values_size
+            }
+            // Protocol::PingMessage(Ping { from, token, signature }) and
+            // Protocol::PongMessage(Pong { from, hash, signature })
+            Self::Ping { .. } | Self::Pong { .. } => {
+                TAG_SIZE + PUBKEY_SIZE + HASH_SIZE + SIGNATURE_SIZE
+            }
+        }
+    }
+}
+
+impl PartialEq for InFlight {
+    fn eq(&self, other: &Self) -> bool {
+        self.delivery_time == other.delivery_time
+    }
+}
+
+impl Eq for InFlight {}
+
+impl PartialOrd for InFlight {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for InFlight {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.delivery_time.cmp(&other.delivery_time)
+    }
 }
 
 #[allow(clippy::type_complexity)]
 pub fn make_gossip_cluster(
     rpc_client: &RpcClient,
-) -> Result<Vec<(Node, Sender<Arc<Packet>>)>, Error> {
+) -> Result<Vec<(Node, Sender<(Instant, Arc<Packet>)>)>, Error> {
+    let nodes = get_cluster_nodes(rpc_client)?;
+    Ok(make_gossip_cluster_from_nodes(&nodes))
+}
+
+/// Loads cluster nodes from a snapshot file, a synthetic cluster generated
+/// from the rng, or json rpc.
+pub fn load_cluster_nodes<R: Rng>(
+    rng: &mut R,
+    source: &ClusterSource,
+) -> Result<Vec<NodeInfo>, Error> {
+    match source {
+        ClusterSource::Snapshot(path) => {
+            info!("snapshot: {}", path.display());
+            Ok(read_snapshot(path)?)
+        }
+        ClusterSource::Synthetic {
+            num_nodes,
+            distribution,
+            unstaked_fraction,
+        } => {
+            info!("stake distribution: {:?}", distribution);
+            Ok(make_synthetic_nodes(
+                rng,
+                *num_nodes,
+                distribution,
+                *unstaked_fraction,
+            ))
+        }
+        ClusterSource::JsonRpc(json_rpc_url) => {
+            info!("json_rpc_url: {}", json_rpc_url);
+            get_cluster_nodes(&RpcClient::new(json_rpc_url.clone()))
+        }
+    }
+}
+
+/// Obtains gossip nodes along with their activated stake from json rpc.
+pub fn get_cluster_nodes(rpc_client: &RpcClient) -> Result<Vec<NodeInfo>, Error> {
     let config = RpcGetVoteAccountsConfig {
         vote_pubkey: None,
         commitment: Some(CommitmentConfig::finalized()),
@@ -382,7 +1526,25 @@ pub fn make_gossip_cluster(
             Some(stake.unwrap_or_default() + vote_account_info.activated_stake)
         });
     info!("num of node pubkeys in vote accounts: {}", stakes.len());
-    let nodes = rpc_client.get_cluster_nodes()?;
+    let active_stake: u64 = stakes.values().sum();
+    info!("active stake:  {}", active_stake);
+    rpc_client
+        .get_cluster_nodes()?
+        .into_iter()
+        .map(|node| {
+            Ok(NodeInfo {
+                pubkey: Pubkey::from_str(&node.pubkey)?,
+                stake: stakes.get(&node.pubkey).copied().unwrap_or_default(),
+                shred_version: node.shred_version,
+            })
+        })
+        .collect()
+}
+
+#[allow(clippy::type_complexity)]
+pub fn make_gossip_cluster_from_nodes(
+    nodes: &[NodeInfo],
+) -> Vec<(Node, Sender<(Instant, Arc<Packet>)>)> {
     let shred_versions: HashSet<_> = nodes.iter().map(|node| node.shred_version).collect();
     if shred_versions.len() > 1 {
         error!("multiple shred versions: {:?}", shred_versions);
@@ -391,35 +1553,18 @@ pub fn make_gossip_cluster(
     }
     let now = Instant::now();
     let nodes: Vec<_> = nodes
-        .into_iter()
-        .map(|node| {
-            let stake = stakes.get(&node.pubkey).copied().unwrap_or_default();
-            let pubkey = Pubkey::from_str(&node.pubkey)?;
-            let (sender, receiver) = crossbeam_channel::unbounded();
-            let node = Node {
-                clock: now,
-                num_gossip_rounds: 0,
-                stake,
-                pubkey,
-                table: HashMap::default(),
-                active_set: PushActiveSet::default(),
-                received_cache: ReceivedCache::new(2 * CRDS_UNIQUE_PUBKEY_CAPACITY),
-                receiver,
-            };
-            Ok((node, sender))
-        })
-        .collect::<Result<_, Error>>()?;
+        .iter()
+        .map(|node| Node::new(now, node.pubkey, node.stake))
+        .collect();
     let num_nodes_staked = nodes
         .iter()
         .filter(|(node, _sender)| node.stake != 0)
         .count();
     info!("num of staked nodes in cluster: {}", num_nodes_staked);
     info!("num of cluster nodes: {}", nodes.len());
-    let active_stake: u64 = stakes.values().sum();
     let cluster_stake: u64 = nodes.iter().map(|(node, _sender)| node.stake).sum();
-    info!("active stake:  {}", active_stake);
     info!("cluster stake: {}", cluster_stake);
-    Ok(nodes)
+    nodes
 }
 
 /// Returns most recent CRDS table across all nodes.
@@ -437,3 +1582,767 @@ where
     }
     out
 }
+
+/// For a sample of the most recent crds values across all nodes, returns
+/// the distribution of time from creation until the value reached each given
+/// fraction of the cluster stake. Fractions must be in increasing order.
+/// Values created less than `horizon` before `now` are skipped since they may
+/// still be propagating.
+pub fn get_stake_coverage_times<T: Borrow<Node>>(
+    nodes: &[T],
+    fractions: &[f64],
+    now: Instant,
+    horizon: Duration,
+    max_num_values: usize,
+) -> Vec<Histogram> {
+    debug_assert!(fractions.windows(2).all(|w| w[0] <= w[1]));
+    let mut values = HashMap::<CrdsKey, (/*ordinal:*/ u64, /*wallclock:*/ Instant)>::new();
+    for node in nodes {
+        for (key, entry) in node.borrow().table() {
+            let value = values
+                .entry(*key)
+                .or_insert((entry.ordinal, entry.wallclock));
+            if value.0 < entry.ordinal {
+                *value = (entry.ordinal, entry.wallclock);
+            }
+        }
+    }
+    let values: Vec<_> = values
+        .into_iter()
+        .filter(|(_, (_, wallclock))| *wallclock + horizon <= now)
+        .sorted_unstable_by_key(|&(key, (_, wallclock))| (Reverse(wallclock), key))
+        .take(max_num_values)
+        .collect();
+    let cluster_stake: u64 = nodes.iter().map(|node| node.borrow().stake).sum();
+    let mut out = vec![Histogram::default(); fractions.len()];
+    for (key, (ordinal, wallclock)) in values {
+        let arrivals = nodes
+            .iter()
+            .map(Borrow::borrow)
+            .filter_map(|node| {
+                let entry = node.table.get(&key)?;
+                (entry.ordinal == ordinal).then_some((entry.local_timestamp, node.stake))
+            })
+            .sorted_unstable_by_key(|&(local_timestamp, _)| local_timestamp)
+            .scan(0u64, |acc, (local_timestamp, stake)| {
+                *acc = acc.saturating_add(stake);
+                Some((local_timestamp, *acc))
+            });
+        let mut fractions = fractions.iter().zip(&mut out).peekable();
+        for (local_timestamp, stake) in arrivals {
+            while let Some((_, histogram)) =
+                fractions.next_if(|(fraction, _)| stake as f64 >= **fraction * cluster_stake as f64)
+            {
+                histogram.add(local_timestamp.saturating_duration_since(wallclock));
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use {
+        super::*,
+        crate::{latency::Latency, peer_sampler::Uniform},
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
+        solana_sdk::native_token::LAMPORTS_PER_SOL,
+    };
+
+    pub(crate) type TestCluster = (
+        ChaChaRng,
+        Instant,
+        Vec<Node>,
+        HashMap<Pubkey, u64>,
+        Router<Arc<Packet>>,
+    );
+
+    pub(crate) fn make_config() -> Config {
+        Config {
+            gossip_push_fanout: 2.0,
+            gossip_push_wide_fanout: 2.0,
+            rotate_active_set_rounds: 1000,
+            gossip_prune_min_ingress_nodes: 2,
+            gossip_prune_stake_threshold: 0.15,
+            received_cache_min_num_upserts: 20,
+            received_cache_entry_capacity: 50,
+            received_cache_num_dups_threshold: 2,
+            push_active_set_size: 6,
+            num_push_active_set_entries: 25,
+            push_active_set_bloom_false_rate: 0.1,
+            push_active_set_bloom_max_bits: 32768,
+            gossip_push_capacity: 1024,
+            packet_drop_rate: 0.0,
+            num_crds: 16,
+            refresh_rate: 0.0,
+            num_threads: 1,
+            run_duration: Duration::ZERO,
+            seed: 0,
+            virtual_time: true,
+            gossip_interval: Duration::from_millis(200),
+            warm_up_rounds: 0,
+            gossip_pull_rounds: 0,
+            gossip_pull_fanout: 1,
+            crds_timeout_staked: Duration::from_secs(60),
+            crds_timeout_unstaked: Duration::from_secs(15),
+            crds_purge_rounds: 0,
+            ping_cache_ttl: Duration::ZERO,
+            ping_cache_rate_limit_delay: Duration::from_secs(1),
+        }
+    }
+
+    // Returns an rng seeded with the given byte, the start time, and staked
+    // nodes along with their router; each node's receiver channel holds the
+    // packets sent to it.
+    pub(crate) fn make_cluster(seed: u8, num_nodes: usize) -> TestCluster {
+        let mut rng = ChaChaRng::from_seed([seed; 32]);
+        let now = Instant::now();
+        let (nodes, senders): (Vec<_>, Vec<_>) = repeat_with(|| {
+            let pubkey = Pubkey::new_from_array(rng.gen());
+            let stake = rng.gen_range(1, 1000) * LAMPORTS_PER_SOL;
+            let (node, sender) = Node::new(now, pubkey, stake);
+            (node, (pubkey, sender))
+        })
+        .take(num_nodes)
+        .unzip();
+        let stakes = nodes.iter().map(|node| (node.pubkey, node.stake)).collect();
+        let router = Router::new(0.0, Latency::default(), senders).unwrap();
+        (rng, now, nodes, stakes, router)
+    }
+
+    pub(crate) fn make_entry(now: Instant, ordinal: u64) -> CrdsEntry {
+        CrdsEntry {
+            ordinal,
+            num_dups: 0u8,
+            wallclock: now,
+            local_timestamp: now,
+            num_hops: 0u8,
+        }
+    }
+
+    pub(crate) fn insert_entry(node: &mut Node, origin: Pubkey, entry: CrdsEntry) {
+        node.table.insert(CrdsKey { origin, index: 0 }, entry);
+    }
+
+    // Returns packets delivered to the node by now.
+    pub(crate) fn recv_packets(node: &Node) -> Vec<Arc<Packet>> {
+        node.receiver.try_iter().map(|(_, packet)| packet).collect()
+    }
+
+    #[test]
+    fn test_push_capacity_defers_keys() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(17, 12);
+        let config = Config {
+            gossip_push_capacity: 5,
+            ..make_config()
+        };
+        let origin = nodes[1].pubkey;
+        let keys: Vec<CrdsKey> = (0..4).map(|index| CrdsKey { origin, index }).collect();
+        for key in &keys {
+            nodes[0].table.insert(*key, make_entry(now, 1));
+        }
+        nodes[0].deferred_keys = keys.clone();
+        nodes[0]
+            .run_gossip(&mut rng, now, &config, &stakes, &router)
+            .unwrap();
+        // Two keys are pushed to their full fanout; the third does not fit in
+        // the remaining budget of one push and is deferred with the fourth.
+        let mut fanouts = HashMap::<CrdsKey, usize>::new();
+        for node in &nodes[1..] {
+            for packet in recv_packets(node) {
+                let Packet::Push { ref values, .. } = *packet else {
+                    panic!("not a push message");
+                };
+                for (key, ..) in values {
+                    *fanouts.entry(*key).or_default() += 1;
+                }
+            }
+        }
+        assert_eq!(fanouts.len(), 2);
+        assert!(fanouts.values().all(|&fanout| fanout == 2));
+        assert_eq!(nodes[0].deferred_keys.len(), 2);
+        assert!(nodes[0]
+            .deferred_keys
+            .iter()
+            .all(|key| !fanouts.contains_key(key)));
+        // Deferred keys are pushed in the next round.
+        let now = now + config.gossip_interval;
+        nodes[0]
+            .run_gossip(&mut rng, now, &config, &stakes, &router)
+            .unwrap();
+        assert!(nodes[0].deferred_keys.is_empty());
+        let num_pushes: usize = nodes[1..]
+            .iter()
+            .flat_map(recv_packets)
+            .map(|packet| match *packet {
+                Packet::Push { ref values, .. } => values.len(),
+                _ => 0,
+            })
+            .sum();
+        assert_eq!(num_pushes, 4);
+        // A budget smaller than a single fanout still pushes one key.
+        let config = Config {
+            gossip_push_capacity: 1,
+            ..make_config()
+        };
+        nodes[0].deferred_keys = keys;
+        let now = now + config.gossip_interval;
+        nodes[0]
+            .run_gossip(&mut rng, now, &config, &stakes, &router)
+            .unwrap();
+        assert_eq!(nodes[0].deferred_keys.len(), 3);
+        assert_eq!(nodes[1..].iter().flat_map(recv_packets).count(), 1);
+    }
+
+    #[test]
+    fn test_pull_request_filters() {
+        let mut rng = ChaChaRng::from_seed([29u8; 32]);
+        for num_items in [0, 1, 100, 2_000, 20_000] {
+            let hashes: Vec<Hash> = repeat_with(|| hashv(&[&rng.gen::<[u8; 32]>()]))
+                .take(num_items)
+                .collect();
+            let filters = make_pull_request_filters(&mut rng, num_items, hashes.iter().copied());
+            let mask_bits = filters[0].2;
+            assert_eq!(filters.len(), 1 << mask_bits);
+            assert_eq!(mask_bits == 0, num_items < 1_000, "{num_items}");
+            for (filter, _, _) in &filters {
+                let packet = Packet::PullRequest {
+                    from: Pubkey::default(),
+                    filter: filter.clone(),
+                    mask: 0,
+                    mask_bits,
+                };
+                assert!(packet.wire_size() <= PACKET_DATA_SIZE);
+            }
+            // Each hash is in exactly the filter whose mask it matches.
+            for hash in &hashes {
+                let mut filters = filters
+                    .iter()
+                    .filter(|(_, mask, mask_bits)| test_mask(hash, *mask, *mask_bits));
+                assert!(filters.next().unwrap().0.contains(hash));
+                assert!(filters.next().is_none());
+            }
+            let num_false_positives = repeat_with(|| hashv(&[&rng.gen::<[u8; 32]>()]))
+                .take(1000)
+                .filter(|hash| {
+                    filters.iter().any(|(filter, mask, mask_bits)| {
+                        test_mask(hash, *mask, *mask_bits) && filter.contains(hash)
+                    })
+                })
+                .count();
+            assert!(num_false_positives < 200, "{num_false_positives}");
+        }
+    }
+
+    #[test]
+    fn test_pull_request_peer_sampler() {
+        let (mut rng, _now, mut nodes, mut stakes, router) = make_cluster(37, 10);
+        let config = make_config();
+        // Only this node and the first peer are staked.
+        for stake in stakes.values_mut() {
+            *stake = 0;
+        }
+        stakes.insert(nodes[0].pubkey, LAMPORTS_PER_SOL << 20);
+        stakes.insert(nodes[1].pubkey, LAMPORTS_PER_SOL << 20);
+        // Returns the number of pull requests sent to the staked peer.
+        let mut num_requests = |node: &mut Node, peer: &Node| {
+            for _ in 0..200 {
+                node.send_pull_requests(&mut rng, &config, &stakes, &router)
+                    .unwrap();
+            }
+            recv_packets(peer).len()
+        };
+        let (node, peers) = nodes.split_first_mut().unwrap();
+        // Stake buckets overwhelmingly favor the staked peer, whereas the
+        // uniform sampler picks it about a tenth of the time.
+        assert!(num_requests(node, &peers[0]) > 180);
+        node.set_peer_sampler(Arc::new(Uniform));
+        assert!(num_requests(node, &peers[0]) < 40);
+    }
+
+    #[test]
+    fn test_pull_responses() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(31, 2);
+        let config = Config {
+            gossip_pull_rounds: 1,
+            ..make_config()
+        };
+        let origin = Pubkey::new_from_array(rng.gen());
+        let keys: Vec<CrdsKey> = (0..400).map(|index| CrdsKey { origin, index }).collect();
+        for key in &keys {
+            nodes[1].table.insert(*key, make_entry(now, 2));
+        }
+        // The requesting node has the current value of the first 100 keys, and
+        // outdated values of the next 100.
+        for key in &keys[..100] {
+            nodes[0].table.insert(*key, make_entry(now, 2));
+        }
+        for key in &keys[100..200] {
+            nodes[0].table.insert(*key, make_entry(now, 1));
+        }
+        nodes[0]
+            .send_pull_requests(&mut rng, &config, &stakes, &router)
+            .unwrap();
+        nodes[1].consume_packets(now, &config, &stakes);
+        nodes[1]
+            .send_pull_responses(&mut rng, &config, &router)
+            .unwrap();
+        // Only values missing from the requesting node are returned, up to
+        // the response capacity, in packets of at most PACKET_DATA_SIZE bytes.
+        let packets = recv_packets(&nodes[0]);
+        assert!(packets.len() > 1);
+        assert!(packets
+            .iter()
+            .all(|packet| packet.wire_size() <= PACKET_DATA_SIZE));
+        let values: Vec<_> = packets
+            .iter()
+            .flat_map(|packet| match **packet {
+                Packet::PullResponse { ref values, .. } => values.clone(),
+                _ => panic!("not a pull response"),
+            })
+            .collect();
+        assert_eq!(values.len(), CRDS_GOSSIP_PULL_RESPONSE_CAPACITY);
+        assert_eq!(
+            values.iter().map(|(key, ..)| key).unique().count(),
+            values.len()
+        );
+        for (key, ordinal, ..) in &values {
+            assert!(key.index >= 100);
+            assert_eq!(*ordinal, 2);
+        }
+        // A node which has all the values gets no response.
+        for key in &keys {
+            nodes[0].table.insert(*key, make_entry(now, 2));
+        }
+        nodes[0]
+            .send_pull_requests(&mut rng, &config, &stakes, &router)
+            .unwrap();
+        nodes[1].consume_packets(now, &config, &stakes);
+        nodes[1]
+            .send_pull_responses(&mut rng, &config, &router)
+            .unwrap();
+        assert!(recv_packets(&nodes[0]).is_empty());
+    }
+
+    #[test]
+    fn test_wire_size() {
+        // LegacyContactInfo, NodeInstance, Version, LowestSlot and
+        // SnapshotHashes, each signed and tagged.
+        assert_eq!(get_crds_value_size(0), 210);
+        assert_eq!(get_crds_value_size(1), 124);
+        assert_eq!(get_crds_value_size(2), 123);
+        assert_eq!(get_crds_value_size(3), 141);
+        assert_eq!(get_crds_value_size(4), 476);
+        assert!((5..=36).all(|index| get_crds_value_size(index) == 459));
+        // EpochSlots fill up MAX_CRDS_OBJECT_SIZE.
+        assert_eq!(get_crds_value_size(37), 928);
+        assert_eq!(get_crds_value_size(usize::MAX), 928);
+        let from = Pubkey::default();
+        let now = Instant::now();
+        let values = |indices: &[usize]| -> Vec<_> {
+            indices
+                .iter()
+                .map(|&index| {
+                    (
+                        CrdsKey {
+                            origin: from,
+                            index,
+                        },
+                        1,
+                        now,
+                        0,
+                    )
+                })
+                .collect()
+        };
+        // PUSH_MESSAGE_MAX_PAYLOAD_SIZE is PACKET_DATA_SIZE - 44.
+        let packet = Packet::Push {
+            from,
+            values: Vec::default(),
+        };
+        assert_eq!(packet.wire_size(), 44);
+        let packet = Packet::Push {
+            from,
+            values: values(&[0, 5, 5]),
+        };
+        assert_eq!(packet.wire_size(), 44 + 210 + 2 * 459);
+        let packet = Packet::PullResponse {
+            from,
+            values: values(&[40]),
+        };
+        assert_eq!(packet.wire_size(), 44 + 928);
+        // PruneData with MAX_PRUNE_DATA_NODES origins fits in a packet.
+        let packet = Packet::Prune {
+            from,
+            origins: vec![from; 32],
+        };
+        assert_eq!(packet.wire_size(), 1204);
+        assert!(packet.wire_size() <= PACKET_DATA_SIZE);
+        let packet = Packet::Ping { from, token: 0 };
+        assert_eq!(packet.wire_size(), 132);
+        let packet = Packet::Pong { from, token: 0 };
+        assert_eq!(packet.wire_size(), 132);
+        // CrdsFilter { filter, mask, mask_bits } with a single word bloom
+        // filter, along with the caller's LegacyContactInfo.
+        let packet = Packet::PullRequest {
+            from,
+            filter: Bloom::new(64, Vec::default()),
+            mask: !0u64,
+            mask_bits: 0,
+        };
+        assert_eq!(packet.wire_size(), 4 + 33 + 8 + 12 + 210);
+        let packet = Packet::PullRequest {
+            from,
+            filter: Bloom::new(6400, vec![0; 8]),
+            mask: !0u64,
+            mask_bits: 0,
+        };
+        assert_eq!(packet.wire_size(), 4 + 33 + 8 * 8 + 800 + 12 + 210);
+    }
+
+    #[test]
+    fn test_push_batches() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(37, 8);
+        let config = make_config();
+        let origin = nodes[1].pubkey;
+        for index in 0..200 {
+            let key = CrdsKey { origin, index };
+            nodes[0].table.insert(key, make_entry(now, 1));
+            nodes[0].deferred_keys.push(key);
+        }
+        nodes[0]
+            .run_gossip(&mut rng, now, &config, &stakes, &router)
+            .unwrap();
+        let packets: Vec<_> = nodes[1..].iter().flat_map(recv_packets).collect();
+        assert!(packets
+            .iter()
+            .all(|packet| packet.wire_size() <= PACKET_DATA_SIZE));
+        let num_values: usize = packets
+            .iter()
+            .map(|packet| match **packet {
+                Packet::Push { ref values, .. } => values.len(),
+                _ => panic!("not a push message"),
+            })
+            .sum();
+        assert_eq!(num_values, 200 * 2);
+        // Small values are batched together, while epoch-slots fill up a
+        // packet on their own: to each destination, the five values of
+        // indices 0..5 share a packet, and votes are batched two per packet.
+        assert_eq!(packets.len(), 2 * (1 + 16 + 163));
+    }
+
+    #[test]
+    fn test_restart() {
+        let (mut rng, now, mut nodes, _stakes, _router) = make_cluster(43, 2);
+        let config = Config {
+            num_crds: 1,
+            refresh_rate: 4.0,
+            ..make_config()
+        };
+        let origin = nodes[1].pubkey;
+        let node = &mut nodes[0];
+        node.table
+            .insert(CrdsKey { origin, index: 0 }, make_entry(now, 1));
+        node.refresh_entries(&mut rng, &config).for_each(drop);
+        // Restarted nodes come back with an empty table, and their values
+        // supersede the ones published before the restart.
+        let key = CrdsKey {
+            origin: node.pubkey,
+            index: 0,
+        };
+        for ordinal in [8, 12] {
+            node.restart(now);
+            assert!(node.table.is_empty());
+            node.refresh_entries(&mut rng, &config).for_each(drop);
+            assert_eq!(node.table.len(), 1);
+            assert_eq!(node.table[&key].ordinal, ordinal);
+        }
+    }
+
+    #[test]
+    fn test_send_attacks() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(47, 6);
+        let config = make_config();
+        let pubkey = nodes[0].pubkey;
+        let origins: Vec<Pubkey> = nodes[1..].iter().map(|node| node.pubkey).collect();
+        for &origin in &origins {
+            for index in 0..4 {
+                let key = CrdsKey { origin, index };
+                nodes[0].table.insert(key, make_entry(now, 5));
+            }
+        }
+        // Attack packets are counted past warm-up rounds.
+        nodes[0].num_gossip_rounds = 1;
+        // Own values are never used in attacks.
+        nodes[0].table.insert(
+            CrdsKey {
+                origin: pubkey,
+                index: 0,
+            },
+            make_entry(now, 1),
+        );
+        for attack in [Attack::Spam, Attack::Replay, Attack::Spoof, Attack::Prune] {
+            nodes[0].attacks = vec![(attack, 20)];
+            nodes[0]
+                .send_attacks(&mut rng, &config, &stakes, &router)
+                .unwrap();
+            let mut num_packets = 0;
+            for node in &nodes[1..] {
+                for packet in recv_packets(node) {
+                    num_packets += 1;
+                    match (attack, &*packet) {
+                        (
+                            Attack::Spam | Attack::Replay | Attack::Spoof,
+                            Packet::Push { from, values },
+                        ) => {
+                            assert!(!values.is_empty());
+                            assert!(packet.wire_size() <= PACKET_DATA_SIZE);
+                            if attack == Attack::Spoof {
+                                assert_ne!(from, &pubkey);
+                                assert_ne!(from, &node.pubkey);
+                                assert!(origins.contains(from));
+                            } else {
+                                assert_eq!(from, &pubkey);
+                            }
+                            for (key, ordinal, ..) in values {
+                                assert_ne!(key.origin, pubkey);
+                                match attack {
+                                    Attack::Spam => assert!(*ordinal < 5),
+                                    Attack::Replay => assert_eq!(*ordinal, 4),
+                                    _ => assert_eq!(*ordinal, 5),
+                                }
+                            }
+                        }
+                        (
+                            Attack::Prune,
+                            Packet::Prune {
+                                from,
+                                origins: pruned,
+                            },
+                        ) => {
+                            assert_ne!(from, &pubkey);
+                            assert_ne!(from, &node.pubkey);
+                            assert!(origins.contains(from));
+                            assert!(!pruned.is_empty());
+                            assert!(pruned.len() <= NUM_BOGUS_PRUNE_ORIGINS);
+                            assert!(!pruned.contains(from));
+                            assert!(!pruned.contains(&node.pubkey));
+                            assert!(!pruned.contains(&pubkey));
+                        }
+                        _ => panic!("unexpected packet"),
+                    }
+                }
+            }
+            assert_eq!(num_packets, 20);
+        }
+        assert_eq!(nodes[0].num_attack_packets(), 80);
+    }
+
+    #[test]
+    fn test_eclipse_victim() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(53, 4);
+        let config = make_config();
+        let victim = nodes[1].pubkey;
+        nodes[0].set_eclipse_victim(victim);
+        // The sybil drops the victim's pushes and pull requests, but not
+        // those of honest nodes.
+        let origin = nodes[3].pubkey;
+        for (k, from) in [victim, nodes[2].pubkey].into_iter().enumerate() {
+            let packets = [
+                Packet::Push {
+                    from,
+                    values: vec![(CrdsKey { origin, index: k }, 1, now, 1)],
+                },
+                Packet::PullRequest {
+                    from,
+                    filter: Bloom::new(64, Vec::default()),
+                    mask: !0u64,
+                    mask_bits: 0,
+                },
+            ];
+            for packet in packets {
+                router
+                    .send(&mut rng, now, &from, &nodes[0].pubkey, Arc::new(packet))
+                    .unwrap();
+            }
+        }
+        let out = nodes[0].consume_packets(now, &config, &stakes);
+        assert_eq!(out.keys, HashSet::from([CrdsKey { origin, index: 1 }]));
+        assert_eq!(nodes[0].pull_requests.len(), 1);
+        // The sybil never pushes to the victim, even though the victim is in
+        // its active set.
+        for index in 0..8 {
+            let key = CrdsKey { origin, index };
+            nodes[0].table.insert(key, make_entry(now, 2));
+            nodes[0].deferred_keys.push(key);
+        }
+        let config = Config {
+            gossip_push_fanout: 3.0,
+            ..config
+        };
+        nodes[0]
+            .run_gossip(&mut rng, now, &config, &stakes, &router)
+            .unwrap();
+        assert!(nodes[0].active_set_nodes().contains(&victim));
+        assert!(recv_packets(&nodes[1])
+            .iter()
+            .all(|packet| !matches!(**packet, Packet::Push { .. })));
+        assert!(recv_packets(&nodes[2])
+            .iter()
+            .any(|packet| matches!(**packet, Packet::Push { .. })));
+    }
+
+    #[test]
+    fn test_purge() {
+        let (mut rng, now, mut nodes, mut stakes, _router) = make_cluster(59, 2);
+        let config = make_config();
+        let unstaked = Pubkey::new_from_array(rng.gen());
+        stakes.insert(unstaked, 0);
+        let staked = nodes[1].pubkey;
+        let node = &mut nodes[0];
+        let origins = [node.pubkey, staked, unstaked];
+        for origin in origins {
+            let key = CrdsKey { origin, index: 0 };
+            node.table.insert(key, make_entry(now, 1));
+            node.deferred_keys.push(key);
+        }
+        let get_origins =
+            |node: &Node| -> HashSet<Pubkey> { node.table.keys().map(|key| key.origin).collect() };
+        // Values are retained up to the timeout of their origin.
+        node.clock = now + config.crds_timeout_unstaked;
+        node.purge(&config, &stakes);
+        assert_eq!(get_origins(node), HashSet::from(origins));
+        // Unstaked values are purged first, and are not pushed anymore.
+        node.clock += Duration::from_millis(1);
+        node.purge(&config, &stakes);
+        assert_eq!(get_origins(node), HashSet::from([node.pubkey, staked]));
+        assert!(node.deferred_keys.iter().all(|key| key.origin != unstaked));
+        node.clock = now + config.crds_timeout_staked;
+        node.purge(&config, &stakes);
+        assert_eq!(get_origins(node), HashSet::from([node.pubkey, staked]));
+        // Staked values are purged past the staked timeout.
+        node.clock += Duration::from_millis(1);
+        node.purge(&config, &stakes);
+        assert_eq!(get_origins(node), HashSet::from([node.pubkey]));
+        // Own values are never purged.
+        node.clock = now + Duration::from_secs(3600);
+        node.purge(&config, &stakes);
+        let key = CrdsKey {
+            origin: node.pubkey,
+            index: 0,
+        };
+        assert_eq!(get_origins(node), HashSet::from([node.pubkey]));
+        assert_eq!(node.deferred_keys, [key]);
+    }
+
+    #[test]
+    fn test_upsert_expired() {
+        let (_rng, now, mut nodes, stakes, _router) = make_cluster(61, 2);
+        let config = make_config();
+        let timeout = config.crds_timeout_staked;
+        let key = CrdsKey {
+            origin: nodes[1].pubkey,
+            index: 0,
+        };
+        let node = &mut nodes[0];
+        // Returns the value created at now, arriving after the given delay.
+        let make_value = |ordinal, delay| CrdsEntry {
+            local_timestamp: now + delay,
+            ..make_entry(now, ordinal)
+        };
+        // Values older than the timeout of their origin are rejected.
+        assert!(matches!(
+            node.upsert(key, make_value(1, timeout * 2), &config, &stakes),
+            Err(UpsertError::Expired)
+        ));
+        assert!(node.table.is_empty());
+        assert!(matches!(
+            node.upsert(key, make_value(1, timeout), &config, &stakes),
+            Ok(())
+        ));
+        // Purged values are not re-inserted if received again.
+        node.clock = now + timeout * 2 + Duration::from_millis(1);
+        node.purge(&config, &stakes);
+        assert!(matches!(
+            node.upsert(key, make_value(1, timeout * 2), &config, &stakes),
+            Err(UpsertError::Expired)
+        ));
+        assert!(node.table.is_empty());
+        // Newer values from the same origin are still inserted.
+        let value = make_entry(node.clock, 2);
+        assert!(matches!(node.upsert(key, value, &config, &stakes), Ok(())));
+        assert_eq!(node.table[&key].ordinal, 2);
+        // Own values never expire.
+        let key = CrdsKey {
+            origin: node.pubkey,
+            index: 0,
+        };
+        assert!(matches!(
+            node.upsert(key, make_value(1, timeout * 2), &config, &stakes),
+            Ok(())
+        ));
+    }
+
+    #[test]
+    fn test_get_gossip_peers() {
+        let (mut rng, now, mut nodes, stakes, _router) = make_cluster(67, 6);
+        let unstaked = Pubkey::new_from_array(rng.gen());
+        let pubkey = nodes[0].pubkey;
+        let node = &mut nodes[0];
+        insert_entry(node, pubkey, make_entry(now, 1));
+        insert_entry(node, unstaked, make_entry(now, 1));
+        // Without entrypoints the node knows all staked nodes.
+        let peers: Vec<Pubkey> = stakes
+            .keys()
+            .copied()
+            .chain([unstaked])
+            .filter(|peer| peer != &pubkey)
+            .sorted_unstable()
+            .collect();
+        assert_eq!(node.get_gossip_peers(&stakes), peers);
+        // With entrypoints the node only knows its entrypoints and the
+        // origins of values in its table, excluding itself.
+        let entrypoint = nodes[1].pubkey;
+        let node = &mut nodes[0];
+        node.set_entrypoints(vec![entrypoint, pubkey]);
+        let peers: Vec<Pubkey> = [entrypoint, unstaked]
+            .into_iter()
+            .sorted_unstable()
+            .collect();
+        assert_eq!(node.get_gossip_peers(&stakes), peers);
+        let origin = nodes[2].pubkey;
+        insert_entry(&mut nodes[0], origin, make_entry(now, 1));
+        let peers: Vec<Pubkey> = [entrypoint, unstaked, origin]
+            .into_iter()
+            .sorted_unstable()
+            .collect();
+        assert_eq!(nodes[0].get_gossip_peers(&stakes), peers);
+    }
+
+    #[test]
+    fn test_ping_pong_admits_peer() {
+        let (mut rng, now, mut nodes, stakes, router) = make_cluster(73, 2);
+        let config = Config {
+            ping_cache_ttl: Duration::from_secs(60),
+            ..make_config()
+        };
+        let (node, peers) = nodes.split_first_mut().unwrap();
+        let peer = &mut peers[0];
+        // The peer is pinged but not admitted until it answers.
+        node.rotate_active_set(&mut rng, &config, &stakes, &router)
+            .unwrap();
+        assert!(node.active_set_nodes().is_empty());
+        let now = now + Duration::from_secs(1);
+        peer.consume_packets(now, &config, &stakes);
+        peer.clock = now;
+        peer.send_pongs(&mut rng, &config, &router).unwrap();
+        let now = now + Duration::from_secs(1);
+        node.consume_packets(now, &config, &stakes);
+        node.clock = now;
+        node.rotate_active_set(&mut rng, &config, &stakes, &router)
+            .unwrap();
+        let active_set: HashSet<Pubkey> = node.active_set_nodes().into_iter().collect();
+        assert_eq!(active_set, HashSet::from([peer.pubkey]));
+    }
+}
@@ -1,50 +1,148 @@
 use {
-    clap::{crate_description, crate_name, App, Arg},
+    clap::{crate_description, crate_name, App, Arg, ArgMatches, ErrorKind},
     cluster_mocks::{
-        gossip::{get_crds_table, make_gossip_cluster, Config, CrdsEntry, Node, Packet},
+        adversary::AdversarySpec,
+        bandwidth::Bandwidth,
+        churn::{write_downtimes, Churn, ChurnMonitor, ChurnSpec, Downtime},
+        discovery::{DiscoveryMonitor, DISCOVERY_FRACTIONS},
+        eclipse::{EclipseMonitor, EclipseSpec},
+        gossip::{
+            get_crds_table, get_stake_coverage_times, load_cluster_nodes,
+            make_gossip_cluster_from_nodes, ClusterSource, Config, CrdsEntry, Node, Packet,
+        },
+        latency::Latency,
+        loss::{GilbertElliott, Loss, LossSpec},
+        partition::{Partition, PartitionMonitor, PartitionSpec},
+        peer_sampler::parse_peer_sampler,
+        propagation::{Histogram, PropagationStats},
+        prune_policy::parse_prune_policy,
+        scenario::{read_scenario, write_scenario, Scenario, ScenarioError, ScenarioValue},
+        scheduler::Scheduler,
+        snapshot::write_snapshot,
+        stats::{write_summary, ClusterStats, NodeStats, StatsFormat, StatsWriter},
+        synthetic::parse_unstaked_fraction,
         Error, Router, API_MAINNET_BETA,
     },
-    log::info,
-    rand::seq::SliceRandom,
+    log::{info, warn},
+    rand::{seq::SliceRandom, Rng, SeedableRng},
+    rand_chacha::ChaChaRng,
     rayon::{prelude::*, ThreadPoolBuilder},
-    solana_client::rpc_client::RpcClient,
     solana_sdk::pubkey::Pubkey,
     std::{
         cmp::Reverse,
-        collections::HashMap,
+        collections::{HashMap, HashSet},
+        ffi::OsString,
+        fmt::Display,
+        ops::{Bound, RangeBounds},
+        path::PathBuf,
+        str::FromStr,
         sync::{Arc, RwLock, TryLockError},
         time::{Duration, Instant},
     },
 };
 
-fn run_gossip(
+// Max number of crds values sampled for time-to-stake-coverage metrics.
+const MAX_NUM_COVERAGE_VALUES: usize = 4096;
+// Fractions of cluster stake for which time-to-coverage is reported.
+const STAKE_COVERAGE_FRACTIONS: [f64; 4] = [0.5, 0.9, 0.99, 1.0];
+
+fn run_gossip<R: Rng>(
+    rng: &mut R,
     config: &Config,
     nodes: &[RwLock<Node>],
     stakes: &HashMap<Pubkey, /*stake:*/ u64>,
     router: &Router<Arc<Packet>>,
 ) -> Result<(), Error> {
-    let mut rng = rand::thread_rng();
     let now = Instant::now();
     while now.elapsed() < config.run_duration {
-        let node = nodes.choose(&mut rng).unwrap();
+        let node = nodes.choose(rng).unwrap();
         let mut node = match node.try_write() {
             Ok(node) => node,
             Err(TryLockError::Poisoned(_)) => return Err(Error::TryLockErrorPoisoned),
             Err(TryLockError::WouldBlock) => continue,
         };
-        node.run_gossip(&mut rng, config, stakes, router)?;
+        node.run_gossip(rng, Instant::now(), config, stakes, router)?;
     }
     Ok(())
 }
 
+// Stats snapshots, partition, churn, eclipse and discovery monitoring,
+// sampled every stats interval.
+struct Sampler {
+    writer: Option<StatsWriter>,
+    monitor: PartitionMonitor,
+    churn: ChurnMonitor,
+    eclipse: EclipseMonitor,
+    discovery: DiscoveryMonitor,
+}
+
+impl Sampler {
+    fn is_empty(&self) -> bool {
+        self.writer.is_none()
+            && self.monitor.is_empty()
+            && self.churn.is_empty()
+            && self.eclipse.is_empty()
+            && self.discovery.is_empty()
+    }
+
+    fn sample(&mut self, elapsed: Duration, nodes: &[&Node]) {
+        if let Some(writer) = self.writer.as_mut() {
+            let stats = ClusterStats::new(elapsed, nodes.iter().copied());
+            writer.write(&stats).unwrap();
+        }
+        self.monitor.sample(elapsed, nodes);
+        self.churn.sample(elapsed, nodes);
+        self.eclipse.sample(elapsed, nodes);
+        self.discovery.sample(elapsed, nodes);
+    }
+}
+
+// Periodically samples the cluster while worker threads run gossip.
+fn run_sampler(
+    sampler: &mut Sampler,
+    interval: Duration,
+    epoch: Instant,
+    run_duration: Duration,
+    nodes: &[RwLock<Node>],
+) {
+    for k in 1.. {
+        let elapsed = interval * k;
+        if elapsed > run_duration {
+            break;
+        }
+        std::thread::sleep((epoch + elapsed).saturating_duration_since(Instant::now()));
+        let nodes: Vec<_> = nodes.iter().map(|node| node.read().unwrap()).collect();
+        let nodes: Vec<&Node> = nodes.iter().map(|node| &**node).collect();
+        sampler.sample(epoch.elapsed(), &nodes);
+    }
+}
+
 fn main() {
     if std::env::var("RUST_LOG").is_err() {
         std::env::set_var("RUST_LOG", "INFO");
     }
     solana_logger::setup();
 
-    let matches = App::new(crate_name!())
+    let app = App::new(crate_name!())
         .about(crate_description!())
+        .arg(
+            Arg::with_name("config")
+                .long("config")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "load the scenario from a toml, or json if ending in .json, file \
+                    of argument names and values; flags given on the command line \
+                    override the file",
+                ),
+        )
+        .arg(
+            Arg::with_name("dump_config")
+                .long("dump-config")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("write the effective scenario to a file which can be loaded with --config"),
+        )
         .arg(
             Arg::with_name("json_rpc_url")
                 .long("url")
@@ -53,12 +151,59 @@ fn main() {
                 .default_value(API_MAINNET_BETA)
                 .help("solana's json rpc url"),
         )
+        .arg(
+            Arg::with_name("snapshot")
+                .long("snapshot")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("load cluster nodes from a snapshot file instead of json rpc"),
+        )
+        .arg(
+            Arg::with_name("dump_snapshot")
+                .long("dump-snapshot")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("write cluster nodes to a snapshot file"),
+        )
+        .arg(
+            Arg::with_name("synthetic")
+                .long("synthetic")
+                .value_name("NUM_NODES")
+                .takes_value(true)
+                .conflicts_with("snapshot")
+                .help("generate a synthetic cluster with the given number of nodes"),
+        )
+        .arg(
+            Arg::with_name("stake_distribution")
+                .long("stake-distribution")
+                .value_name("SPEC")
+                .takes_value(true)
+                .default_value("pareto:1000,1.2")
+                .help(
+                    "stake distribution of synthetic cluster in SOL: uniform:MIN,MAX \
+                    | pareto:MIN,ALPHA | zipf:MAX,EXPONENT | snapshot:PATH",
+                ),
+        )
+        .arg(
+            Arg::with_name("unstaked_fraction")
+                .long("unstaked-fraction")
+                .takes_value(true)
+                .default_value("0.0")
+                .validator(parse_unstaked_fraction)
+                .help("fraction of unstaked nodes in synthetic cluster"),
+        )
         .arg(
             Arg::with_name("num_threads")
                 .long("num-threads")
                 .takes_value(true)
                 .help("number of worker threads"),
         )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("rng seed to reproduce a run; random if not specified"),
+        )
         .arg(
             Arg::with_name("run_duration")
                 .long("run-duration")
@@ -66,6 +211,19 @@ fn main() {
                 .default_value("1")
                 .help("simulation duration (minutes)"),
         )
+        .arg(
+            Arg::with_name("virtual_time")
+                .long("virtual-time")
+                .takes_value(false)
+                .help("run a deterministic discrete-event simulation in virtual time"),
+        )
+        .arg(
+            Arg::with_name("gossip_interval")
+                .long("gossip-interval")
+                .takes_value(true)
+                .default_value("200")
+                .help("delay between gossip rounds of each node in virtual time [ms]"),
+        )
         .arg(
             Arg::with_name("gossip_push_fanout")
                 .long("gossip-push-fanout")
@@ -93,12 +251,80 @@ fn main() {
                 .default_value("3")
                 .help("Min ingress number of nodes to keep when pruning received-cache"),
         )
+        .arg(
+            Arg::with_name("gossip_prune_stake_threshold")
+                .long("gossip-prune-stake-threshold")
+                .takes_value(true)
+                .default_value("0.15")
+                .validator(|arg| parse_in_range(arg, 0.0..=1.0))
+                .help(
+                    "min fraction, in [0, 1], of min stake of {this node, crds value owner} \
+                    to keep in aggregate across ingress nodes when pruning received-cache",
+                ),
+        )
+        .arg(
+            Arg::with_name("received_cache_min_num_upserts")
+                .long("received-cache-min-num-upserts")
+                .takes_value(true)
+                .default_value("20")
+                .help("min number of upserts of an origin before its ingress nodes are pruned"),
+        )
+        .arg(
+            Arg::with_name("received_cache_entry_capacity")
+                .long("received-cache-entry-capacity")
+                .takes_value(true)
+                .default_value("50")
+                .help("max number of ingress nodes tracked for each origin"),
+        )
+        .arg(
+            Arg::with_name("received_cache_num_dups_threshold")
+                .long("received-cache-num-dups-threshold")
+                .takes_value(true)
+                .default_value("2")
+                .help(
+                    "number of duplicates before which a delivery counts as timely \
+                    towards the ingress node's score",
+                ),
+        )
+        .arg(
+            Arg::with_name("push_active_set_size")
+                .long("push-active-set-size")
+                .takes_value(true)
+                .help("number of nodes in each push active-set entry [default: 3 x gossip push fanout]"),
+        )
+        .arg(
+            Arg::with_name("num_push_active_set_entries")
+                .long("num-push-active-set-entries")
+                .takes_value(true)
+                .default_value("25")
+                .validator(|arg| parse_in_range(arg, 1usize..))
+                .help("number of stake buckets, each with its own push active-set entry"),
+        )
+        .arg(
+            Arg::with_name("push_active_set_bloom_false_rate")
+                .long("push-active-set-bloom-false-rate")
+                .takes_value(true)
+                .default_value("0.1")
+                .validator(|arg| parse_in_range(arg, (Bound::Excluded(0.0), Bound::Excluded(1.0))))
+                .help("false positive rate, in (0, 1), of bloom filters of pruned origins"),
+        )
+        .arg(
+            Arg::with_name("push_active_set_bloom_max_bits")
+                .long("push-active-set-bloom-max-bits")
+                .takes_value(true)
+                .default_value("32768")
+                .validator(|arg| parse_in_range(arg, 1usize..))
+                .help("max number of bits in bloom filters of pruned origins"),
+        )
         .arg(
             Arg::with_name("gossip_push_capacity")
                 .long("gossip-push-capacity")
                 .takes_value(true)
-                .default_value("5")
-                .help("gossip push fanout"),
+                .default_value("4096")
+                .help(
+                    "maximum number of crds values to push in each gossip round, \
+                    counting each destination separately",
+                ),
         )
         .arg(
             Arg::with_name("packet_drop_rate")
@@ -107,6 +333,17 @@ fn main() {
                 .default_value("0.0")
                 .help("packet drop probability"),
         )
+        .arg(
+            Arg::with_name("latency")
+                .long("latency")
+                .value_name("SPEC")
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "packet delivery latency: MS | fixed:MS | uniform:MIN_MS,MAX_MS \
+                    | lognormal:MEDIAN_MS,SIGMA | matrix:PATH",
+                ),
+        )
         .arg(
             Arg::with_name("num_crds")
                 .long("num-crds")
@@ -127,12 +364,265 @@ fn main() {
                 .takes_value(true)
                 .help("number of gossip rounds before collecting stats"),
         )
-        .get_matches();
+        .arg(
+            Arg::with_name("gossip_pull_rounds")
+                .long("gossip-pull-rounds")
+                .takes_value(true)
+                .default_value("0")
+                .help("number of gossip rounds between pull requests; 0 disables pull"),
+        )
+        .arg(
+            Arg::with_name("gossip_pull_fanout")
+                .long("gossip-pull-fanout")
+                .takes_value(true)
+                .default_value("1")
+                .help("number of peers to send pull requests to in each pull round"),
+        )
+        .arg(
+            Arg::with_name("crds_timeout_staked")
+                .long("crds-timeout-staked")
+                .value_name("MS")
+                .takes_value(true)
+                .default_value("172800000")
+                .help("crds timeout of values from staked origins; defaults to an epoch"),
+        )
+        .arg(
+            Arg::with_name("crds_timeout_unstaked")
+                .long("crds-timeout-unstaked")
+                .value_name("MS")
+                .takes_value(true)
+                .default_value("15000")
+                .help("crds timeout of values from unstaked origins"),
+        )
+        .arg(
+            Arg::with_name("crds_purge_rounds")
+                .long("crds-purge-rounds")
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "number of gossip rounds between purging crds values older \
+                    than the crds timeout; 0 disables purging",
+                ),
+        )
+        .arg(
+            Arg::with_name("peer_sampler")
+                .long("peer-sampler")
+                .value_name("SAMPLER")
+                .takes_value(true)
+                .default_value("bucket")
+                .validator(parse_peer_sampler)
+                .help(
+                    "how gossip peers are weighted when rotating the push active set: \
+                    bucket[:EXPONENT] ((stake bucket + 1)^EXPONENT, 2 in solana-gossip), \
+                    stake, log-stake, or uniform",
+                ),
+        )
+        .arg(
+            Arg::with_name("prune_policy")
+                .long("prune-policy")
+                .value_name("POLICY")
+                .takes_value(true)
+                .default_value("stake")
+                .validator(parse_prune_policy)
+                .help(
+                    "which ingress nodes to prune: stake (solana-gossip), score \
+                    (ignoring stake), latency (ranked by latency-weighted \
+                    score), never, or random:PROBABILITY (each stake prune \
+                    applied with the probability)",
+                ),
+        )
+        .arg(
+            Arg::with_name("ping_cache_ttl")
+                .long("ping-cache-ttl")
+                .value_name("MS")
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "only admit peers into the push active set if they answered \
+                    a ping within the ttl, as solana-gossip does with 1280000ms; \
+                    0 disables ping/pong verification",
+                ),
+        )
+        .arg(
+            Arg::with_name("ping_cache_rate_limit_delay")
+                .long("ping-cache-rate-limit-delay")
+                .value_name("MS")
+                .takes_value(true)
+                .default_value("20000")
+                .help("minimum delay between pings to the same peer"),
+        )
+        .arg(
+            Arg::with_name("stats_format")
+                .long("stats-format")
+                .takes_value(true)
+                .possible_values(["csv", "json"])
+                .default_value("json")
+                .help("format of stats output files"),
+        )
+        .arg(
+            Arg::with_name("stats_output")
+                .long("stats-output")
+                .value_name("PATH")
+                .takes_value(true)
+                .help(
+                    "write final cluster-wide and per-node stats to the file; \
+                    in json format, also the effective scenario and config, \
+                    otherwise the effective scenario is written alongside with \
+                    a .scenario.toml extension",
+                ),
+        )
+        .arg(
+            Arg::with_name("stats_snapshots")
+                .long("stats-snapshots")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("periodically write cluster-wide stats to the file"),
+        )
+        .arg(
+            Arg::with_name("stats_interval")
+                .long("stats-interval")
+                .takes_value(true)
+                .default_value("10000")
+                .help(
+                    "interval between stats snapshots in milliseconds; \
+                    also the resolution of partition reconvergence times",
+                ),
+        )
+        .arg(
+            Arg::with_name("partition")
+                .long("partition")
+                .value_name("NODES,START,END[,DROP_RATE]")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help(
+                    "drop packets between NODES and the rest of the cluster \
+                    from START until END; NODES is random:FRACTION or file:PATH, \
+                    START and END are e.g. 1500ms, 30s or 100r (gossip rounds)",
+                ),
+        )
+        .arg(
+            Arg::with_name("loss")
+                .long("loss")
+                .value_name("SPEC")
+                .takes_value(true)
+                .help(
+                    "per-node and per-link packet loss on top of packet drop rate; \
+                    SPEC is random:FRACTION,DROP_RATE or file:PATH with lines \
+                    egress,PUBKEY,DROP_RATE, ingress,PUBKEY,DROP_RATE \
+                    or link,FROM,TO,DROP_RATE",
+                ),
+        )
+        .arg(
+            Arg::with_name("burst_loss")
+                .long("burst-loss")
+                .value_name("P,R[,H]")
+                .takes_value(true)
+                .help(
+                    "Gilbert-Elliott bursty loss on each link; P and R are the \
+                    probabilities of entering and leaving the bad state, \
+                    and H is the drop rate in the bad state",
+                ),
+        )
+        .arg(
+            Arg::with_name("bandwidth")
+                .long("bandwidth")
+                .value_name("EGRESS,INGRESS[,QUEUE_SIZE]")
+                .takes_value(true)
+                .help(
+                    "cap each node's egress and ingress bandwidth in Mbps, \
+                    tail-dropping packets once QUEUE_SIZE KB are queued",
+                ),
+        )
+        .arg(
+            Arg::with_name("churn")
+                .long("churn")
+                .value_name("SPEC")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help(
+                    "take nodes offline during the run; SPEC is \
+                    restart:RATE,DOWNTIME with RATE restarts per node per minute, \
+                    leave:FRACTION, join:FRACTION, or file:PATH with lines \
+                    PUBKEY,START[,END]",
+                ),
+        )
+        .arg(
+            Arg::with_name("dump_churn")
+                .long("dump-churn")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("write the churn schedule to a file which can be replayed"),
+        )
+        .arg(
+            Arg::with_name("adversary")
+                .long("adversary")
+                .value_name("ATTACK:FRACTION[,RATE]")
+                .takes_value(true)
+                .multiple_occurrences(true)
+                .help(
+                    "make a fraction of nodes malicious, each sending RATE attack \
+                    packets per gossip round on top of honest gossip; ATTACK is \
+                    spam (stale values), replay (previous ordinals), spoof (pushes \
+                    with forged from) or prune (forged prunes of honest origins)",
+                ),
+        )
+        .arg(
+            Arg::with_name("num_entrypoints")
+                .long("num-entrypoints")
+                .takes_value(true)
+                .default_value("0")
+                .help(
+                    "number of random nodes which every node starts knowing, \
+                    discovering other peers through crds values they receive; \
+                    0 lets all nodes know all staked nodes from start",
+                ),
+        )
+        .arg(
+            Arg::with_name("eclipse")
+                .long("eclipse")
+                .value_name("NUM_NODES[,STAKE[,VICTIM]]")
+                .takes_value(true)
+                .help(
+                    "add NUM_NODES sybil nodes holding STAKE fraction of the \
+                    cluster stake, which try to occupy the VICTIM's push active \
+                    set and drop its traffic; VICTIM defaults to a random node",
+                ),
+        );
+    let matches = {
+        let matches = app.clone().get_matches();
+        match matches.value_of("config") {
+            None => matches,
+            Some(path) => {
+                info!("scenario: {}", path);
+                // Invalid scenario files are reported the same way as invalid
+                // arguments.
+                let exit = |err: ScenarioError| -> ! {
+                    app.clone()
+                        .error(ErrorKind::InvalidValue, format!("--config {path}: {err}"))
+                        .exit()
+                };
+                let mut scenario = read_scenario(path).unwrap_or_else(|err| exit(err));
+                // Flags given on the command line override the scenario file.
+                // Unknown names are retained so that parsing fails on them.
+                scenario.retain(|name| {
+                    let name = name.replace('_', "-");
+                    app.get_arguments()
+                        .find(|arg| arg.get_long() == Some(&name))
+                        .map(|arg| matches.occurrences_of(arg.get_id()) == 0)
+                        .unwrap_or(true)
+                });
+                let mut args: Vec<OsString> = std::env::args_os().collect();
+                let scenario = scenario
+                    .to_args()
+                    .unwrap_or_else(|err| exit(err))
+                    .into_iter()
+                    .map(OsString::from);
+                args.splice(1..1, scenario);
+                app.clone().get_matches_from(args)
+            }
+        }
+    };
 
-    let json_rpc_url =
-        cluster_mocks::get_json_rpc_url(matches.value_of("json_rpc_url").unwrap_or_default());
-    info!("json_rpc_url: {}", json_rpc_url);
-    let rpc_client = RpcClient::new(json_rpc_url);
     let config = {
         let num_crds = matches.value_of_t_or_exit("num_crds");
         let gossip_push_fanout = matches.value_of_t_or_exit("gossip_push_fanout");
@@ -144,6 +634,22 @@ fn main() {
             rotate_active_set_rounds: matches.value_of_t_or_exit("rotate_active_set_rounds"),
             gossip_prune_min_ingress_nodes: matches
                 .value_of_t_or_exit("gossip_prune_min_ingress_nodes"),
+            gossip_prune_stake_threshold: matches
+                .value_of_t_or_exit("gossip_prune_stake_threshold"),
+            received_cache_min_num_upserts: matches
+                .value_of_t_or_exit("received_cache_min_num_upserts"),
+            received_cache_entry_capacity: matches
+                .value_of_t_or_exit("received_cache_entry_capacity"),
+            received_cache_num_dups_threshold: matches
+                .value_of_t_or_exit("received_cache_num_dups_threshold"),
+            push_active_set_size: matches
+                .value_of_t("push_active_set_size")
+                .unwrap_or(gossip_push_fanout as usize * 3),
+            num_push_active_set_entries: matches.value_of_t_or_exit("num_push_active_set_entries"),
+            push_active_set_bloom_false_rate: matches
+                .value_of_t_or_exit("push_active_set_bloom_false_rate"),
+            push_active_set_bloom_max_bits: matches
+                .value_of_t_or_exit("push_active_set_bloom_max_bits"),
             gossip_push_capacity: matches.value_of_t_or_exit("gossip_push_capacity"),
             packet_drop_rate: matches.value_of_t_or_exit("packet_drop_rate"),
             num_crds,
@@ -154,12 +660,80 @@ fn main() {
             run_duration: Duration::from_secs(
                 matches.value_of_t_or_exit::<u64>("run_duration") * 60,
             ),
+            seed: matches
+                .value_of_t("seed")
+                .unwrap_or_else(|_| rand::thread_rng().gen()),
+            virtual_time: matches.is_present("virtual_time"),
+            gossip_interval: Duration::from_millis(matches.value_of_t_or_exit("gossip_interval")),
             warm_up_rounds: matches.value_of_t("warm_up_rounds").unwrap_or(2 * num_crds),
+            gossip_pull_rounds: matches.value_of_t_or_exit("gossip_pull_rounds"),
+            gossip_pull_fanout: matches.value_of_t_or_exit("gossip_pull_fanout"),
+            crds_timeout_staked: Duration::from_millis(
+                matches.value_of_t_or_exit("crds_timeout_staked"),
+            ),
+            crds_timeout_unstaked: Duration::from_millis(
+                matches.value_of_t_or_exit("crds_timeout_unstaked"),
+            ),
+            crds_purge_rounds: matches.value_of_t_or_exit("crds_purge_rounds"),
+            ping_cache_ttl: Duration::from_millis(matches.value_of_t_or_exit("ping_cache_ttl")),
+            ping_cache_rate_limit_delay: Duration::from_millis(
+                matches.value_of_t_or_exit("ping_cache_rate_limit_delay"),
+            ),
         }
     };
     info!("config: {:#?}", config);
+    let scenario = get_effective_scenario(&app, &matches, &config);
+    info!("scenario: {:?}", scenario);
     assert!(config.num_threads > 0);
-    let nodes = make_gossip_cluster(&rpc_client).unwrap();
+    assert!(config.gossip_interval > Duration::ZERO);
+    if let Some(path) = matches.value_of("dump_config") {
+        write_scenario(path, &scenario).unwrap();
+    }
+    if config.virtual_time
+        && config.run_duration.as_nanos() / config.gossip_interval.as_nanos()
+            <= config.warm_up_rounds as u128
+    {
+        warn!("run duration is too short to collect stats past warm-up rounds");
+    }
+    // Synthetic cluster is generated from a stream separate from the ones
+    // used by the simulation.
+    let mut rng = ChaChaRng::seed_from_u64(config.seed);
+    rng.set_stream(u64::MAX);
+    let source = match (
+        matches.value_of("snapshot"),
+        matches.is_present("synthetic"),
+    ) {
+        (Some(path), _) => ClusterSource::Snapshot(PathBuf::from(path)),
+        (None, true) => ClusterSource::Synthetic {
+            num_nodes: matches.value_of_t_or_exit("synthetic"),
+            distribution: matches.value_of_t_or_exit("stake_distribution"),
+            unstaked_fraction: matches.value_of_t_or_exit("unstaked_fraction"),
+        },
+        (None, false) => ClusterSource::JsonRpc(
+            cluster_mocks::get_json_rpc_url(matches.value_of("json_rpc_url").unwrap_or_default())
+                .to_string(),
+        ),
+    };
+    let nodes = load_cluster_nodes(&mut rng, &source).unwrap();
+    if let Some(path) = matches.value_of("dump_snapshot") {
+        write_snapshot(path, &nodes).unwrap();
+    }
+    // Sybil nodes are not included in the dumped snapshot.
+    let (nodes, eclipse) = match matches.value_of("eclipse") {
+        None => (nodes, None),
+        Some(spec) => {
+            let spec = EclipseSpec::from_str(spec).unwrap();
+            let (sybils, eclipse) = spec.resolve(&mut rng, &nodes);
+            info!(
+                "eclipse: victim: {}, sybils: {}, stake: {}",
+                eclipse.victim,
+                sybils.len(),
+                sybils.iter().map(|node| node.stake).sum::<u64>(),
+            );
+            (nodes.into_iter().chain(sybils).collect(), Some(eclipse))
+        }
+    };
+    let nodes = make_gossip_cluster_from_nodes(&nodes);
     let (nodes, senders): (Vec<_>, Vec<_>) = nodes
         .into_iter()
         .map(|(node, sender)| {
@@ -167,32 +741,203 @@ fn main() {
             (node, (pubkey, sender))
         })
         .unzip();
-    let router = Router::new(config.packet_drop_rate, senders).unwrap();
+    let latency: Latency = matches.value_of_t_or_exit("latency");
+    info!("latency: {:?}", latency);
+    let partitions: Vec<Partition> = {
+        let mut pubkeys: Vec<_> = nodes.iter().map(Node::pubkey).collect();
+        pubkeys.sort_unstable();
+        matches
+            .values_of("partition")
+            .into_iter()
+            .flatten()
+            .map(|spec| {
+                let spec = PartitionSpec::from_str(spec).unwrap();
+                spec.resolve(&mut rng, &pubkeys, config.gossip_interval)
+                    .unwrap()
+            })
+            .collect()
+    };
+    for partition in &partitions {
+        info!(
+            "partition: {} nodes, {:?}..{:?}, drop rate: {}",
+            partition.nodes.len(),
+            partition.start,
+            partition.end,
+            partition.drop_rate
+        );
+    }
+    let loss = {
+        let mut pubkeys: Vec<_> = nodes.iter().map(Node::pubkey).collect();
+        pubkeys.sort_unstable();
+        let loss = match matches.value_of("loss") {
+            None => Loss::default(),
+            Some(spec) => {
+                let spec = LossSpec::from_str(spec).unwrap();
+                spec.resolve(&mut rng, &pubkeys)
+            }
+        };
+        match matches.value_of("burst_loss") {
+            None => loss,
+            Some(spec) => loss.with_burst(GilbertElliott::from_str(spec).unwrap()),
+        }
+    };
+    let downtimes: Vec<Downtime> = {
+        let mut pubkeys: Vec<_> = nodes.iter().map(Node::pubkey).collect();
+        pubkeys.sort_unstable();
+        matches
+            .values_of("churn")
+            .into_iter()
+            .flatten()
+            .flat_map(|spec| {
+                let spec = ChurnSpec::from_str(spec).unwrap();
+                spec.resolve(
+                    &mut rng,
+                    &pubkeys,
+                    config.gossip_interval,
+                    config.run_duration,
+                )
+            })
+            .collect()
+    };
+    info!("churn: {} downtimes", downtimes.len());
+    if let Some(path) = matches.value_of("dump_churn") {
+        write_downtimes(path, &downtimes).unwrap();
+    }
+    let lossy_nodes = loss.nodes();
+    info!("lossy nodes: {}", lossy_nodes.len());
+    let nodes = {
+        let mut nodes = nodes;
+        let mut pubkeys: Vec<_> = nodes.iter().map(Node::pubkey).collect();
+        pubkeys.sort_unstable();
+        // Adversaries are drawn from a stream of their own, so that the rest
+        // of the setup is the same as in a run without adversaries.
+        let mut adversary_rng = ChaChaRng::seed_from_u64(config.seed);
+        adversary_rng.set_stream(u64::MAX - 1);
+        for spec in matches.values_of("adversary").into_iter().flatten() {
+            let spec = AdversarySpec::from_str(spec).unwrap();
+            let adversaries: HashSet<Pubkey> = spec
+                .resolve(&mut adversary_rng, &pubkeys)
+                .into_iter()
+                .collect();
+            info!("adversary: {:?}, {} nodes", spec, adversaries.len());
+            for node in &mut nodes {
+                if adversaries.contains(&node.pubkey()) {
+                    node.add_attack(spec.attack, spec.rate);
+                }
+            }
+        }
+        if let Some(eclipse) = &eclipse {
+            for node in &mut nodes {
+                if eclipse.sybils.contains(&node.pubkey()) {
+                    node.set_eclipse_victim(eclipse.victim);
+                }
+            }
+        }
+        let prune_policy = parse_prune_policy(matches.value_of("prune_policy").unwrap()).unwrap();
+        let peer_sampler = parse_peer_sampler(matches.value_of("peer_sampler").unwrap()).unwrap();
+        for node in &mut nodes {
+            node.set_prune_policy(prune_policy.clone());
+            node.set_peer_sampler(peer_sampler.clone());
+        }
+        let num_entrypoints: usize = matches.value_of_t_or_exit("num_entrypoints");
+        if num_entrypoints != 0 {
+            let entrypoints: Vec<_> = pubkeys
+                .choose_multiple(&mut rng, num_entrypoints)
+                .copied()
+                .collect();
+            info!("entrypoints: {:?}", entrypoints);
+            for node in &mut nodes {
+                node.set_entrypoints(entrypoints.clone());
+            }
+        }
+        nodes
+    };
+    let bandwidth: Option<Bandwidth> = matches
+        .is_present("bandwidth")
+        .then(|| matches.value_of_t_or_exit("bandwidth"));
+    info!("bandwidth: {:?}", bandwidth);
+    let epoch = Instant::now();
+    let router = Router::new(config.packet_drop_rate, latency, senders)
+        .unwrap()
+        .with_loss(loss)
+        .with_partitions(epoch, partitions.clone())
+        .with_churn(Churn::new(&downtimes));
+    let router = match bandwidth {
+        None => router,
+        Some(bandwidth) => router.with_bandwidth(bandwidth),
+    };
     // TODO: remove unstaked here?!
     let stakes: HashMap<Pubkey, /*stake:*/ u64> = nodes
         .iter()
         .map(|node| (node.pubkey(), node.stake()))
         .collect();
-    let nodes: Vec<_> = nodes.into_iter().map(RwLock::new).collect();
     let thread_pool = ThreadPoolBuilder::new()
         .num_threads(config.num_threads)
         .build()
         .unwrap();
-    thread_pool
-        .broadcast(|_ctx| run_gossip(&config, &nodes, &stakes, &router))
-        .into_iter()
-        .collect::<Result<Vec<()>, Error>>()
-        .unwrap();
+    let stats_format: StatsFormat = matches.value_of_t_or_exit("stats_format");
+    let stats_interval = Duration::from_millis(matches.value_of_t_or_exit("stats_interval"));
+    assert!(stats_interval > Duration::ZERO);
+    let mut sampler = Sampler {
+        writer: matches
+            .value_of("stats_snapshots")
+            .map(|path| StatsWriter::new(path, stats_format).unwrap()),
+        monitor: PartitionMonitor::new(partitions),
+        churn: ChurnMonitor::new(Churn::new(&downtimes)),
+        eclipse: EclipseMonitor::new(eclipse),
+        discovery: DiscoveryMonitor::new(
+            matches.value_of_t_or_exit::<usize>("num_entrypoints") != 0,
+        ),
+    };
+    let (mut nodes, now) = if config.virtual_time {
+        let mut nodes = nodes;
+        let mut rng = ChaChaRng::seed_from_u64(config.seed);
+        let mut scheduler = Scheduler::new(&mut rng, epoch, nodes.len(), config.gossip_interval);
+        // Run in steps of stats interval so that snapshots can be taken in
+        // between; this does not change the simulation results.
+        while scheduler.elapsed() < config.run_duration {
+            let until = (scheduler.elapsed() + stats_interval).min(config.run_duration);
+            scheduler
+                .run_until(&mut rng, until, &config, &mut nodes, &stakes, &router)
+                .unwrap();
+            if !sampler.is_empty() {
+                let nodes: Vec<&Node> = nodes.iter().collect();
+                sampler.sample(scheduler.elapsed(), &nodes);
+            }
+        }
+        (nodes, scheduler.now())
+    } else {
+        let nodes: Vec<_> = nodes.into_iter().map(RwLock::new).collect();
+        std::thread::scope(|scope| {
+            if !sampler.is_empty() {
+                let (sampler, nodes) = (&mut sampler, &nodes);
+                scope.spawn(move || {
+                    run_sampler(sampler, stats_interval, epoch, config.run_duration, nodes)
+                });
+            }
+            thread_pool
+                .broadcast(|ctx| {
+                    // Each worker thread draws from its own stream of the seed.
+                    let mut rng = ChaChaRng::seed_from_u64(config.seed);
+                    rng.set_stream(ctx.index() as u64);
+                    run_gossip(&mut rng, &config, &nodes, &stakes, &router)
+                })
+                .into_iter()
+                .collect::<Result<Vec<()>, Error>>()
+                .unwrap();
+        });
+        let nodes: Vec<_> = nodes
+            .into_iter()
+            .map(RwLock::into_inner)
+            .collect::<Result<_, _>>()
+            .unwrap();
+        (nodes, Instant::now())
+    };
     info!("run_gossip done!");
-    let mut nodes: Vec<_> = nodes
-        .into_iter()
-        .map(RwLock::into_inner)
-        .collect::<Result<_, _>>()
-        .unwrap();
     // Consume packets buffered at each node's receiver channel.
     thread_pool.install(|| {
         nodes.par_iter_mut().for_each(|node| {
-            node.consume_packets(&stakes);
+            node.consume_packets(now, &config, &stakes);
         })
     });
     info!("consume_packets done!");
@@ -202,21 +947,457 @@ fn main() {
     // For each node compute how fresh its CRDS table is.
     nodes.sort_unstable_by_key(|node| Reverse(node.stake()));
     let active_stake: u64 = nodes.iter().map(|node| node.stake()).sum();
-    println!("node     | stake | rounds |   table | crds");
-    println!("-------------------------------------------");
-    for node in &nodes {
+    let elapsed = now.saturating_duration_since(epoch);
+    let get_freshness = |node: &Node| {
         let node_table = node.table();
         let num_hits = table
             .iter()
             .filter(|(key, ordinal)| node_table.get(key).map(CrdsEntry::ordinal) == Some(**ordinal))
             .count();
+        num_hits as f64 / table.len() as f64
+    };
+    println!("node     | stake | rounds |   table | crds | pull | out kB/s |  in kB/s");
+    println!("------------------------------------------------------------------------");
+    for node in &nodes {
+        let [bytes_sent, bytes_received] = get_bytes_per_sec(node, config.warm_up_rounds, elapsed);
         println!(
-            "{} | {:.2}% | {:6} | {:7} | {:2}%",
+            "{} | {:.2}% | {:6} | {:7} | {:3}% | {:3.0}% | {:8.1} | {:8.1}",
             &format!("{}", node.pubkey())[..8],
             node.stake() as f64 * 100.0 / active_stake as f64,
             node.num_gossip_rounds(),
-            node_table.len(),
-            num_hits * 100 / table.len(),
+            node.table().len(),
+            (get_freshness(node) * 100.0) as usize,
+            get_pull_ratio(node.stats()),
+            bytes_sent / 1000.0,
+            bytes_received / 1000.0,
+        );
+    }
+    // Packet counters aggregated across all nodes, excluding warm-up rounds.
+    let cluster_stats = ClusterStats::new(elapsed, &nodes);
+    let stats = &cluster_stats.stats;
+    let get_ratio = |num| {
+        if stats.num_push_values == 0 {
+            0.0
+        } else {
+            num as f64 * 100.0 / stats.num_push_values as f64
+        }
+    };
+    println!();
+    println!(
+        "packets: {}, push values: {}, prunes: {}, outdated: {}, {:.0}%, \
+        duplicates: {}, {:.0}%",
+        stats.num_packets,
+        stats.num_push_values,
+        stats.num_prunes,
+        stats.num_outdated,
+        get_ratio(stats.num_outdated),
+        stats.num_duplicates,
+        get_ratio(stats.num_duplicates),
+    );
+    println!(
+        "pushes sent: {}, prunes sent: {}, mean active set size: {:.1}",
+        stats.num_pushes_sent,
+        stats.num_prunes_sent,
+        stats.active_set_size as f64 / nodes.len() as f64,
+    );
+    println!(
+        "pull requests sent: {}, pull responses sent: {}, \
+        upserts: push: {}, pull: {}, {:.1}%",
+        stats.num_pull_requests_sent,
+        stats.num_pull_responses_sent,
+        stats.num_push_upserts,
+        stats.num_pull_upserts,
+        get_pull_ratio(stats),
+    );
+    // Fanout slots wasted on peers which are offline or unreachable.
+    println!(
+        "push drops: {}, {:.1}% of pushes sent, pings sent: {}",
+        stats.num_push_drops,
+        if stats.num_pushes_sent == 0 {
+            0.0
+        } else {
+            stats.num_push_drops as f64 * 100.0 / stats.num_pushes_sent as f64
+        },
+        stats.num_pings_sent,
+    );
+    println!(
+        "crds purged: {}, expired: {}, mean table size: {:.0}",
+        stats.num_purged,
+        stats.num_expired,
+        stats.table_size as f64 / nodes.len() as f64,
+    );
+    {
+        let (bytes_sent, bytes_received): (Vec<f64>, Vec<f64>) = nodes
+            .iter()
+            .map(|node| {
+                let [bytes_sent, bytes_received] =
+                    get_bytes_per_sec(node, config.warm_up_rounds, elapsed);
+                (bytes_sent, bytes_received)
+            })
+            .unzip();
+        let format = |mut rates: Vec<f64>| {
+            rates.sort_unstable_by(f64::total_cmp);
+            let mean = rates.iter().sum::<f64>() / rates.len() as f64;
+            format!(
+                "mean: {:.1}kB/s, p50: {:.1}kB/s, max: {:.1}kB/s",
+                mean / 1000.0,
+                rates[rates.len() / 2] / 1000.0,
+                rates.last().copied().unwrap_or_default() / 1000.0,
+            )
+        };
+        println!("egress: {}", format(bytes_sent));
+        println!("ingress: {}", format(bytes_received));
+    }
+    if bandwidth.is_some() {
+        let mut queueing_delay = Histogram::default();
+        for node in &nodes {
+            queueing_delay.merge(node.queueing_delay());
+        }
+        println!(
+            "congestion drops: egress: {}, ingress: {}, queueing delay: {}",
+            stats.num_egress_drops,
+            stats.num_ingress_drops,
+            format_histogram(&queueing_delay),
         );
     }
+    // Whether received-cache pruning penalizes badly connected or restarted
+    // nodes beyond their share of pushes.
+    let churned_nodes = sampler.churn.nodes();
+    if !lossy_nodes.is_empty() || !churned_nodes.is_empty() {
+        let mut num_pruned_origins = HashMap::<Pubkey, usize>::new();
+        for node in &nodes {
+            for (peer, count) in node.num_pruned_origins() {
+                *num_pruned_origins.entry(*peer).or_default() += count;
+            }
+        }
+        for (name, group) in [("lossy", &lossy_nodes), ("churned", &churned_nodes)] {
+            if group.is_empty() {
+                continue;
+            }
+            let (group, other): (Vec<&Node>, Vec<&Node>) = nodes
+                .iter()
+                .partition(|node| group.contains(&node.pubkey()));
+            print_prune_fairness(name, &group, &num_pruned_origins, &stakes, active_stake);
+            print_prune_fairness("other", &other, &num_pruned_origins, &stakes, active_stake);
+        }
+    }
+    // Degradation of honest nodes under attack; compare against a run with
+    // the same seed and no adversaries, which has the same cluster setup
+    // although gossip itself diverges once attack packets are sent.
+    let (adversaries, honest): (Vec<&Node>, Vec<&Node>) =
+        nodes.iter().partition(|node| node.is_adversary());
+    if !adversaries.is_empty() {
+        let num_attack_packets: usize = adversaries
+            .iter()
+            .map(|node| node.num_attack_packets())
+            .sum();
+        println!(
+            "adversaries: {}, attack packets sent: {}",
+            adversaries.len(),
+            num_attack_packets
+        );
+        let honest_stats = ClusterStats::new(elapsed, honest.iter().copied());
+        let stats = &honest_stats.stats;
+        let format_ms =
+            |ms: Option<u64>| ms.map_or_else(|| String::from("-"), |ms| format!("{ms}ms"));
+        let get_ratio = |num| {
+            if stats.num_push_values == 0 {
+                0.0
+            } else {
+                num as f64 * 100.0 / stats.num_push_values as f64
+            }
+        };
+        let freshness: f64 = honest.iter().map(|node| get_freshness(node)).sum();
+        println!(
+            "honest nodes: {}, mean freshness: {:.1}%, outdated: {:.0}%, \
+            duplicates: {:.0}%, prunes received: {}, \
+            propagation latency: p50: {}, p90: {}, p99: {}",
+            honest.len(),
+            freshness * 100.0 / honest.len().max(1) as f64,
+            get_ratio(stats.num_outdated),
+            get_ratio(stats.num_duplicates),
+            stats.num_prunes,
+            format_ms(honest_stats.latency_p50_ms),
+            format_ms(honest_stats.latency_p90_ms),
+            format_ms(honest_stats.latency_p99_ms),
+        );
+    }
+    if let Some(eclipse) = sampler.eclipse.eclipse() {
+        // Victim's active set and freshness over time; how many rotations the
+        // sybils need to occupy the active set.
+        let victim = &format!("{}", eclipse.victim)[..8];
+        println!();
+        println!("eclipse {victim} | rotations | sybils | freshness | honest");
+        println!("----------------------------------------------------------");
+        for sample in sampler.eclipse.samples() {
+            println!(
+                "{:>16?} | {:9} | {:5.1}% | {:8.1}% | {:5.1}%",
+                sample.elapsed,
+                sample.num_rotations,
+                sample.sybil_fraction * 100.0,
+                sample.victim_freshness * 100.0,
+                sample.honest_freshness * 100.0,
+            );
+        }
+        for fraction in [0.5, 1.0] {
+            let rotations = sampler
+                .eclipse
+                .samples()
+                .iter()
+                .find(|sample| sample.sybil_fraction >= fraction)
+                .map(|sample| sample.num_rotations.to_string())
+                .unwrap_or_else(|| String::from("-"));
+            println!(
+                "sybils >= {:.0}% of active set after rotations: {}",
+                fraction * 100.0,
+                rotations
+            );
+        }
+        println!();
+    }
+    if !sampler.discovery.is_empty() {
+        // Bootstrapping from entrypoints; discovery times are only as precise
+        // as the stats interval.
+        let discovered = sampler.discovery.discovered();
+        println!(
+            "discovered peers: mean: {:.1}%, min: {:.1}%",
+            discovered.iter().sum::<f64>() * 100.0 / discovered.len().max(1) as f64,
+            discovered
+                .iter()
+                .copied()
+                .reduce(f64::min)
+                .unwrap_or_default()
+                * 100.0,
+        );
+        for (fraction, times) in DISCOVERY_FRACTIONS
+            .iter()
+            .zip(sampler.discovery.discovery_times())
+        {
+            println!(
+                "discovered {:.0}% of peers: nodes: {}, time: {}",
+                fraction * 100.0,
+                times.count(),
+                format_histogram(times),
+            );
+        }
+    }
+    if !sampler.churn.is_empty() {
+        let report = sampler.churn.get_report();
+        println!(
+            "restarts: {}, caught up: {}, catch-up time: {}",
+            report.num_restarts,
+            report.catch_up_times.count(),
+            format_histogram(&report.catch_up_times),
+        );
+    }
+    // Reconvergence of crds tables once partitions heal.
+    for (k, (partition, report)) in sampler
+        .monitor
+        .partitions()
+        .iter()
+        .zip(sampler.monitor.get_reports())
+        .enumerate()
+    {
+        let stake: u64 = partition
+            .nodes
+            .iter()
+            .filter_map(|node| stakes.get(node))
+            .sum();
+        println!(
+            "partition {}: nodes: {}, stake: {:.2}%, {:?}..{:?}, drop rate: {}, \
+            min freshness: {}, reconverged after: {}",
+            k,
+            partition.nodes.len(),
+            stake as f64 * 100.0 / active_stake as f64,
+            partition.start,
+            partition.end,
+            partition.drop_rate,
+            report
+                .min_freshness
+                .map(|freshness| format!("{:.1}%", freshness * 100.0))
+                .unwrap_or_else(|| String::from("-")),
+            report
+                .reconvergence_time
+                .map(|time| format!("{}ms", time.as_millis()))
+                .unwrap_or_else(|| String::from("-")),
+        );
+    }
+    if let Some(path) = matches.value_of("stats_output") {
+        write_summary(
+            path,
+            stats_format,
+            &scenario,
+            &config,
+            &cluster_stats,
+            &nodes,
+        )
+        .unwrap();
+    }
+    // Propagation latency and hop counts aggregated across all nodes.
+    let mut propagation = PropagationStats::default();
+    for node in &nodes {
+        propagation.merge(node.propagation());
+    }
+    println!();
+    println!(
+        "propagation latency: {}",
+        format_histogram(&propagation.latency)
+    );
+    let num_values: u64 = propagation.num_hops.iter().sum();
+    println!("hops | values");
+    println!("-------------");
+    for (num_hops, count) in propagation.num_hops.iter().enumerate() {
+        println!(
+            "{:4} | {:.2}%",
+            num_hops,
+            *count as f64 * 100.0 / num_values as f64
+        );
+    }
+    // Values older than the max observed latency have had enough time to
+    // propagate through the cluster.
+    let horizon = propagation.latency.max().unwrap_or_default();
+    let coverage = get_stake_coverage_times(
+        &nodes,
+        &STAKE_COVERAGE_FRACTIONS,
+        now,
+        horizon,
+        MAX_NUM_COVERAGE_VALUES,
+    );
+    println!();
+    for (fraction, histogram) in STAKE_COVERAGE_FRACTIONS.iter().zip(&coverage) {
+        println!(
+            "time to {:.0}% of stake: {}, values: {}",
+            fraction * 100.0,
+            format_histogram(histogram),
+            histogram.count(),
+        );
+    }
+}
+
+// Returns values of all arguments describing the scenario, including defaults
+// and the seed and number of threads if not specified.
+fn get_effective_scenario(app: &App, matches: &ArgMatches, config: &Config) -> Scenario {
+    // Arguments which name output files rather than describe the scenario.
+    const OUTPUT_ARGS: [&str; 6] = [
+        "config",
+        "dump_config",
+        "dump_churn",
+        "dump_snapshot",
+        "stats_output",
+        "stats_snapshots",
+    ];
+    let mut scenario = Scenario::default();
+    for arg in app.get_arguments() {
+        let name = arg.get_id();
+        // Scenario files name arguments by their long flag.
+        let Some(long) = arg.get_long() else {
+            continue;
+        };
+        if OUTPUT_ARGS.contains(&name) || name == "help" || name == "version" {
+            continue;
+        }
+        if !arg.is_takes_value_set() {
+            scenario.insert(long, ScenarioValue::Flag(matches.is_present(name)));
+            continue;
+        }
+        let Some(values) = matches.values_of(name) else {
+            continue;
+        };
+        let mut values: Vec<_> = values
+            .map(|value| ScenarioValue::Value(value.to_string()))
+            .collect();
+        let value = if arg.is_multiple_occurrences_set() {
+            ScenarioValue::Values(values)
+        } else {
+            values.swap_remove(0)
+        };
+        scenario.insert(long, value);
+    }
+    scenario.insert("seed", ScenarioValue::Value(config.seed.to_string()));
+    scenario.insert(
+        "num_threads",
+        ScenarioValue::Value(config.num_threads.to_string()),
+    );
+    scenario
+}
+
+// Percentage of crds values upserted through pull responses as opposed to
+// push messages.
+fn get_pull_ratio(stats: &NodeStats) -> f64 {
+    let num_upserts = stats.num_push_upserts + stats.num_pull_upserts;
+    if num_upserts == 0 {
+        0.0
+    } else {
+        stats.num_pull_upserts as f64 * 100.0 / num_upserts as f64
+    }
+}
+
+// Egress and ingress bytes per second of the node, over the time elapsed
+// since its warm-up rounds.
+fn get_bytes_per_sec(node: &Node, warm_up_rounds: usize, elapsed: Duration) -> [f64; 2] {
+    let num_rounds = node.num_gossip_rounds();
+    if num_rounds <= warm_up_rounds {
+        return [0.0; 2];
+    }
+    let secs = elapsed.as_secs_f64() * (num_rounds - warm_up_rounds) as f64 / num_rounds as f64;
+    let stats = node.stats();
+    [stats.num_bytes_sent, stats.num_bytes_received].map(|num_bytes| num_bytes as f64 / secs)
+}
+
+fn print_prune_fairness(
+    name: &str,
+    nodes: &[&Node],
+    num_pruned_origins: &HashMap<Pubkey, usize>,
+    stakes: &HashMap<Pubkey, u64>,
+    active_stake: u64,
+) {
+    let pubkeys: HashSet<Pubkey> = nodes.iter().map(|node| node.pubkey()).collect();
+    let stake: u64 = pubkeys.iter().filter_map(|node| stakes.get(node)).sum();
+    let num_pushes: usize = nodes.iter().map(|node| node.stats().num_pushes_sent).sum();
+    let num_pruned: usize = pubkeys
+        .iter()
+        .filter_map(|node| num_pruned_origins.get(node))
+        .sum();
+    println!(
+        "{} nodes: {}, stake: {:.2}%, pushes sent: {}, pruned origins: {}, \
+        per 1000 pushes: {:.1}",
+        name,
+        nodes.len(),
+        stake as f64 * 100.0 / active_stake as f64,
+        num_pushes,
+        num_pruned,
+        if num_pushes == 0 {
+            0.0
+        } else {
+            num_pruned as f64 * 1000.0 / num_pushes as f64
+        },
+    );
+}
+
+fn format_histogram(histogram: &Histogram) -> String {
+    let format = |duration: Option<Duration>| match duration {
+        None => String::from("-"),
+        Some(duration) => format!("{}ms", duration.as_millis()),
+    };
+    format!(
+        "p50: {}, p90: {}, p99: {}, max: {}",
+        format(histogram.percentile(0.5)),
+        format(histogram.percentile(0.9)),
+        format(histogram.percentile(0.99)),
+        format(histogram.max()),
+    )
+}
+
+// Parses a number within the given range, for validating arguments.
+fn parse_in_range<T, R>(arg: &str, range: R) -> Result<T, String>
+where
+    T: FromStr + PartialOrd,
+    T::Err: Display,
+    R: RangeBounds<T>,
+{
+    let value = T::from_str(arg.trim()).map_err(|err| err.to_string())?;
+    if !range.contains(&value) {
+        return Err(format!("{} is out of range", arg.trim()));
+    }
+    Ok(value)
 }
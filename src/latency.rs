@@ -0,0 +1,204 @@
+use {
+    rand::Rng,
+    solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
+    std::{
+        collections::HashMap,
+        f64::consts::PI,
+        fs::File,
+        io::{BufRead, BufReader},
+        num::{ParseFloatError, ParseIntError},
+        path::Path,
+        str::FromStr,
+        time::Duration,
+    },
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum LatencyError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid latency spec: {0}")]
+    InvalidSpec(String),
+    #[error("invalid latency matrix line: {0}")]
+    InvalidMatrixLine(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+    #[error(transparent)]
+    ParsePubkeyError(#[from] ParsePubkeyError),
+}
+
+// Delay between a packet being sent and it being visible at the receiver.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Latency {
+    Fixed(Duration),
+    // Uniformly distributed in [min, max].
+    Uniform { min: Duration, max: Duration },
+    // exp(N(ln(median), sigma^2)), which has a long tail as in WAN links.
+    LogNormal { median: Duration, sigma: f64 },
+    // Per (sender, receiver) latency. Missing pairs fall back to the
+    // reverse direction and are otherwise delivered without delay.
+    Matrix(HashMap<(/*from:*/ Pubkey, /*to:*/ Pubkey), Duration>),
+}
+
+impl Latency {
+    pub(crate) fn sample<R: Rng>(&self, rng: &mut R, from: &Pubkey, to: &Pubkey) -> Duration {
+        match self {
+            Self::Fixed(delay) => *delay,
+            Self::Uniform { min, max } => {
+                let delay = rng.gen_range(min.as_nanos() as u64, max.as_nanos() as u64 + 1);
+                Duration::from_nanos(delay)
+            }
+            Self::LogNormal { median, sigma } => {
+                // Box-Muller transform for a standard normal sample.
+                let u = 1.0 - rng.gen::<f64>(); // in (0, 1]
+                let v = rng.gen::<f64>();
+                let z = (-2.0 * u.ln()).sqrt() * (2.0 * PI * v).cos();
+                median.mul_f64((sigma * z).exp())
+            }
+            Self::Matrix(delays) => delays
+                .get(&(*from, *to))
+                .or_else(|| delays.get(&(*to, *from)))
+                .copied()
+                .unwrap_or_default(),
+        }
+    }
+
+    // Loads per-pair latencies from a csv file with lines:
+    //     from-pubkey,to-pubkey,latency-ms
+    pub fn from_matrix_file<P: AsRef<Path>>(path: P) -> Result<Self, LatencyError> {
+        let mut delays = HashMap::new();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            let [from, to, delay] = fields[..] else {
+                return Err(LatencyError::InvalidMatrixLine(line.to_string()));
+            };
+            let delay = parse_millis(delay)?;
+            delays.insert((Pubkey::from_str(from)?, Pubkey::from_str(to)?), delay);
+        }
+        Ok(Self::Matrix(delays))
+    }
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self::Fixed(Duration::ZERO)
+    }
+}
+
+// Parses latency specs of the form:
+//     MS | fixed:MS | uniform:MIN_MS,MAX_MS | lognormal:MEDIAN_MS,SIGMA
+//     | matrix:PATH
+impl FromStr for Latency {
+    type Err = LatencyError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid_spec = || LatencyError::InvalidSpec(spec.to_string());
+        let (kind, args) = spec.split_once(':').unwrap_or(("fixed", spec));
+        let args: Vec<_> = args.split(',').map(str::trim).collect();
+        match (kind, &args[..]) {
+            ("fixed", [delay]) => Ok(Self::Fixed(parse_millis(delay)?)),
+            ("uniform", [min, max]) => {
+                let (min, max) = (parse_millis(min)?, parse_millis(max)?);
+                if min > max {
+                    return Err(invalid_spec());
+                }
+                Ok(Self::Uniform { min, max })
+            }
+            ("lognormal", [median, sigma]) => {
+                let sigma = f64::from_str(sigma)?;
+                if !sigma.is_finite() || sigma < 0.0 {
+                    return Err(invalid_spec());
+                }
+                let median = parse_millis(median)?;
+                Ok(Self::LogNormal { median, sigma })
+            }
+            ("matrix", [path]) => Self::from_matrix_file(path),
+            _ => Err(invalid_spec()),
+        }
+    }
+}
+
+fn parse_millis(millis: &str) -> Result<Duration, LatencyError> {
+    Ok(Duration::from_millis(u64::from_str(millis)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng};
+
+    #[test]
+    fn test_parse_latency() {
+        assert_eq!(
+            Latency::from_str("70").unwrap(),
+            Latency::Fixed(Duration::from_millis(70))
+        );
+        assert_eq!(
+            Latency::from_str("fixed:0").unwrap(),
+            Latency::Fixed(Duration::ZERO)
+        );
+        assert_eq!(
+            Latency::from_str("uniform:20,180").unwrap(),
+            Latency::Uniform {
+                min: Duration::from_millis(20),
+                max: Duration::from_millis(180),
+            }
+        );
+        assert_eq!(
+            Latency::from_str("lognormal:80,0.5").unwrap(),
+            Latency::LogNormal {
+                median: Duration::from_millis(80),
+                sigma: 0.5,
+            }
+        );
+        for spec in ["", "uniform:20", "uniform:180,20", "lognormal:80,-1", "x:1"] {
+            assert!(Latency::from_str(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_sample_latency() {
+        let mut rng = ChaChaRng::from_seed([71u8; 32]);
+        let from = Pubkey::new_unique();
+        let to = Pubkey::new_unique();
+        let latency = Latency::Uniform {
+            min: Duration::from_millis(20),
+            max: Duration::from_millis(180),
+        };
+        for _ in 0..1000 {
+            let delay = latency.sample(&mut rng, &from, &to);
+            assert!(delay >= Duration::from_millis(20));
+            assert!(delay <= Duration::from_millis(180));
+        }
+        let latency = Latency::LogNormal {
+            median: Duration::from_millis(80),
+            sigma: 0.5,
+        };
+        let num_below = (0..10_000)
+            .filter(|_| latency.sample(&mut rng, &from, &to) < Duration::from_millis(80))
+            .count();
+        assert!((4_800..5_200).contains(&num_below), "{num_below}");
+        let other = Pubkey::new_unique();
+        let latency = Latency::Matrix(
+            [((from, to), Duration::from_millis(42))]
+                .into_iter()
+                .collect(),
+        );
+        assert_eq!(
+            latency.sample(&mut rng, &from, &to),
+            Duration::from_millis(42)
+        );
+        assert_eq!(
+            latency.sample(&mut rng, &to, &from),
+            Duration::from_millis(42)
+        );
+        assert_eq!(latency.sample(&mut rng, &from, &other), Duration::ZERO);
+    }
+}
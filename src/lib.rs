@@ -1,18 +1,48 @@
 use {
+    crate::{
+        bandwidth::{Bandwidth, Links},
+        churn::Churn,
+        latency::Latency,
+        loss::Loss,
+        partition::Partition,
+        snapshot::SnapshotError,
+    },
     crossbeam_channel::Sender,
     rand::Rng,
     solana_client::client_error::ClientError,
     solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
-    std::{collections::HashMap, fmt::Debug},
+    std::{
+        collections::HashMap,
+        fmt::Debug,
+        sync::Arc,
+        time::{Duration, Instant},
+    },
     thiserror::Error,
 };
 
 pub const API_MAINNET_BETA: &str = "https://api.mainnet-beta.solana.com";
 pub const API_TESTNET: &str = "https://api.testnet.solana.com";
 
+pub mod adversary;
+pub mod bandwidth;
+pub mod churn;
+pub mod discovery;
+pub mod eclipse;
 pub mod gossip;
+pub mod latency;
+pub mod loss;
+pub mod partition;
+pub mod peer_sampler;
+mod ping_cache;
+pub mod propagation;
+pub mod prune_policy;
 mod push_active_set;
 mod received_cache;
+pub mod scenario;
+pub mod scheduler;
+pub mod snapshot;
+pub mod stats;
+pub mod synthetic;
 
 #[derive(Debug, Error)]
 pub enum Error {
@@ -22,6 +52,8 @@ pub enum Error {
     ParsePubkeyError(#[from] ParsePubkeyError),
     #[error(transparent)]
     RouterError(#[from] RouterError),
+    #[error(transparent)]
+    SnapshotError(#[from] SnapshotError),
     #[error("TryLockErrorPoisoned")]
     TryLockErrorPoisoned,
 }
@@ -38,45 +70,177 @@ pub enum RouterError {
     SendError,
 }
 
+// Number of bytes a packet occupies on the wire.
+pub trait WireSize {
+    fn wire_size(&self) -> usize;
+}
+
+// Outcome of sending a packet through the router.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Delivery {
+    // Time spent in egress and ingress queues, including transmission time.
+    Delivered { queueing_delay: Duration },
+    // Dropped by packet loss, partitions or an offline receiver.
+    Dropped,
+    // Tail-dropped at a full egress or ingress queue.
+    EgressQueueFull,
+    IngressQueueFull,
+}
+
 pub struct Router<T> {
+    latency: Latency,
     packet_drop_rate: f64,
-    senders: HashMap<Pubkey, Sender<T>>,
+    // Per-node, per-link and bursty loss on top of the packet drop rate.
+    loss: Loss,
+    // Simulation start time, against which partition windows are measured.
+    epoch: Instant,
+    partitions: Vec<Partition>,
+    // Nodes offline during the simulation; packets sent to them are dropped.
+    churn: Churn,
+    // Bandwidth limited egress and ingress queues; unlimited if None.
+    links: Option<Links>,
+    senders: HashMap<Pubkey, Sender<(/*delivery time:*/ Instant, T)>>,
 }
 
 impl<T> Router<T> {
-    pub fn new<I>(packet_drop_rate: f64, nodes: I) -> Result<Self, RouterError>
+    pub fn new<I>(packet_drop_rate: f64, latency: Latency, nodes: I) -> Result<Self, RouterError>
     where
-        I: IntoIterator<Item = (Pubkey, Sender<T>)>,
+        I: IntoIterator<Item = (Pubkey, Sender<(Instant, T)>)>,
     {
         if !(0.0..=1.0).contains(&packet_drop_rate) {
             return Err(RouterError::InvalidPacketDropRate(packet_drop_rate));
         }
-        let mut senders = HashMap::<Pubkey, Sender<T>>::new();
+        let mut senders = HashMap::<Pubkey, Sender<(Instant, T)>>::new();
         for (pubkey, sender) in nodes {
             if senders.insert(pubkey, sender).is_some() {
                 return Err(RouterError::DuplicatePubkey(pubkey));
             }
         }
         Ok(Self {
+            latency,
             packet_drop_rate,
+            loss: Loss::default(),
+            epoch: Instant::now(),
+            partitions: Vec::default(),
+            churn: Churn::default(),
+            links: None,
             senders,
         })
     }
+
+    pub fn with_loss(self, loss: Loss) -> Self {
+        Self { loss, ..self }
+    }
+
+    pub fn with_bandwidth(self, bandwidth: Bandwidth) -> Self {
+        let links = Links::new(bandwidth, self.senders.keys());
+        Self {
+            links: Some(links),
+            ..self
+        }
+    }
+
+    pub fn with_churn(self, churn: Churn) -> Self {
+        Self { churn, ..self }
+    }
+
+    pub fn with_partitions(self, epoch: Instant, partitions: Vec<Partition>) -> Self {
+        Self {
+            epoch,
+            partitions,
+            ..self
+        }
+    }
 }
 
-impl<T> Router<T> {
-    fn send<R: Rng>(&self, rng: &mut R, node: &Pubkey, data: T) -> Result<(), RouterError> {
-        // TODO: How to simulate packets arriving with delay?
-        match self.senders.get(node) {
-            None => Err(RouterError::NodeNotFound(*node)),
-            Some(route) => {
-                if rng.gen_bool(self.packet_drop_rate) {
-                    Ok(()) // Silently drop packet
-                } else {
-                    route.send(data).map_err(|_| RouterError::SendError)
-                }
-            }
+impl<T: WireSize> Router<T> {
+    // Sends the packet along with the time it should become visible at the
+    // receiving end; the receiver is responsible for holding it until then.
+    fn send<R: Rng>(
+        &self,
+        rng: &mut R,
+        now: Instant,
+        from: &Pubkey,
+        node: &Pubkey,
+        data: T,
+    ) -> Result<Delivery, RouterError> {
+        let Some(route) = self.senders.get(node) else {
+            return Err(RouterError::NodeNotFound(*node));
+        };
+        let size = data.wire_size();
+        // Time the packet leaves the sending node.
+        let departure = match &self.links {
+            None => now,
+            Some(links) => match links.send(now, from, size) {
+                None => return Ok(Delivery::EgressQueueFull),
+                Some(departure) => departure,
+            },
+        };
+        if self.is_offline(now, node)
+            || rng.gen_bool(self.packet_drop_rate)
+            || self.loss.drop(rng, from, node)
+            || self.is_partitioned(rng, now, from, node)
+        {
+            return Ok(Delivery::Dropped); // Silently drop packet
         }
+        let delay = self.latency.sample(rng, from, node);
+        let delivery_time = match &self.links {
+            None => departure + delay,
+            Some(links) => match links.recv(departure + delay, node, size) {
+                None => return Ok(Delivery::IngressQueueFull),
+                Some(delivery_time) => delivery_time,
+            },
+        };
+        route
+            .send((delivery_time, data))
+            .map_err(|_| RouterError::SendError)?;
+        Ok(Delivery::Delivered {
+            queueing_delay: delivery_time - now - delay,
+        })
+    }
+}
+
+impl<T> Router<T> {
+    pub(crate) fn is_offline(&self, now: Instant, node: &Pubkey) -> bool {
+        let elapsed = now.saturating_duration_since(self.epoch);
+        self.churn.is_offline(elapsed, node)
+    }
+
+    // Returns the latest time in (since, now] at which the node came back
+    // online.
+    pub(crate) fn get_restart(
+        &self,
+        since: Instant,
+        now: Instant,
+        node: &Pubkey,
+    ) -> Option<Instant> {
+        let since = since.saturating_duration_since(self.epoch);
+        let elapsed = now.saturating_duration_since(self.epoch);
+        let restart = self.churn.get_restart(since, elapsed, node)?;
+        Some(self.epoch + restart)
+    }
+
+    // Returns true if the packet should be dropped because it crosses the
+    // boundary of an active partition.
+    fn is_partitioned<R: Rng>(
+        &self,
+        rng: &mut R,
+        now: Instant,
+        from: &Pubkey,
+        node: &Pubkey,
+    ) -> bool {
+        let elapsed = now.saturating_duration_since(self.epoch);
+        self.partitions
+            .iter()
+            .filter(|partition| partition.is_active(elapsed))
+            .filter(|partition| partition.separates(from, node))
+            .any(|partition| rng.gen_bool(partition.drop_rate))
+    }
+}
+
+impl<T: WireSize> WireSize for Arc<T> {
+    fn wire_size(&self) -> usize {
+        T::wire_size(self)
     }
 }
 
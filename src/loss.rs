@@ -0,0 +1,268 @@
+use {
+    rand::{seq::SliceRandom, Rng},
+    solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
+    std::{
+        collections::{HashMap, HashSet},
+        fs::File,
+        io::{BufRead, BufReader},
+        num::ParseFloatError,
+        path::Path,
+        str::FromStr,
+        sync::Mutex,
+    },
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum LossError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid loss spec: {0}")]
+    InvalidSpec(String),
+    #[error("invalid loss file line: {0}")]
+    InvalidLine(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParsePubkeyError(#[from] ParsePubkeyError),
+}
+
+// Packet loss on top of the cluster-wide packet drop rate. Each of the
+// applicable rates below independently drops the packet.
+#[derive(Debug, Default)]
+pub struct Loss {
+    // Drop rate of packets sent by the node.
+    egress: HashMap<Pubkey, f64>,
+    // Drop rate of packets received by the node.
+    ingress: HashMap<Pubkey, f64>,
+    links: HashMap<(/*from:*/ Pubkey, /*to:*/ Pubkey), f64>,
+    burst: Option<GilbertElliott>,
+}
+
+// Gilbert-Elliott model of bursty loss where each link alternates between a
+// good state without loss and a bad state with high loss.
+#[derive(Debug)]
+pub struct GilbertElliott {
+    // Probability of the link transitioning from good to bad state on each
+    // packet.
+    p: f64,
+    // Probability of the link transitioning from bad to good state on each
+    // packet.
+    r: f64,
+    // Drop rate while in the bad state.
+    h: f64,
+    // Links currently in the bad state.
+    bad_links: Mutex<HashSet<(/*from:*/ Pubkey, /*to:*/ Pubkey)>>,
+}
+
+// Loss model as specified on the command line, before nodes are sampled.
+#[derive(Debug)]
+pub enum LossSpec {
+    File(Box<Loss>),
+    // Random fraction of the cluster nodes with the given egress and ingress
+    // drop rate.
+    Random { fraction: f64, drop_rate: f64 },
+}
+
+impl Loss {
+    // Reads a csv file with lines of the form:
+    //     egress,PUBKEY,DROP_RATE
+    //     ingress,PUBKEY,DROP_RATE
+    //     link,FROM,TO,DROP_RATE
+    // Blank lines and lines starting with '#' are ignored.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Self, LossError> {
+        let mut loss = Self::default();
+        for line in BufReader::new(File::open(path)?).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let invalid_line = || LossError::InvalidLine(line.to_string());
+            let fields: Vec<_> = line.split(',').map(str::trim).collect();
+            match fields[..] {
+                ["egress", node, drop_rate] => {
+                    let node = Pubkey::from_str(node)?;
+                    loss.egress.insert(node, parse_drop_rate(drop_rate)?);
+                }
+                ["ingress", node, drop_rate] => {
+                    let node = Pubkey::from_str(node)?;
+                    loss.ingress.insert(node, parse_drop_rate(drop_rate)?);
+                }
+                ["link", from, to, drop_rate] => {
+                    let link = (Pubkey::from_str(from)?, Pubkey::from_str(to)?);
+                    loss.links.insert(link, parse_drop_rate(drop_rate)?);
+                }
+                _ => return Err(invalid_line()),
+            }
+        }
+        Ok(loss)
+    }
+
+    pub fn with_burst(self, burst: GilbertElliott) -> Self {
+        Self {
+            burst: Some(burst),
+            ..self
+        }
+    }
+
+    // Returns nodes with egress, ingress or per-link loss.
+    pub fn nodes(&self) -> HashSet<Pubkey> {
+        self.egress
+            .keys()
+            .chain(self.ingress.keys())
+            .chain(self.links.keys().flat_map(|(from, to)| [from, to]))
+            .copied()
+            .collect()
+    }
+
+    // Returns true if the packet should be dropped.
+    pub(crate) fn drop<R: Rng>(&self, rng: &mut R, from: &Pubkey, to: &Pubkey) -> bool {
+        let mut gen_drop = |drop_rate: Option<&f64>| match drop_rate {
+            None => false,
+            Some(drop_rate) => rng.gen_bool(*drop_rate),
+        };
+        if gen_drop(self.egress.get(from))
+            || gen_drop(self.ingress.get(to))
+            || gen_drop(self.links.get(&(*from, *to)))
+        {
+            return true;
+        }
+        match &self.burst {
+            None => false,
+            Some(burst) => burst.drop(rng, from, to),
+        }
+    }
+}
+
+impl GilbertElliott {
+    fn drop<R: Rng>(&self, rng: &mut R, from: &Pubkey, to: &Pubkey) -> bool {
+        let mut bad_links = self.bad_links.lock().unwrap();
+        let link = (*from, *to);
+        let is_bad = if bad_links.contains(&link) {
+            !rng.gen_bool(self.r) || !bad_links.remove(&link)
+        } else {
+            rng.gen_bool(self.p) && bad_links.insert(link)
+        };
+        is_bad && rng.gen_bool(self.h)
+    }
+}
+
+impl LossSpec {
+    pub fn resolve<R: Rng>(self, rng: &mut R, nodes: &[Pubkey]) -> Loss {
+        match self {
+            Self::File(loss) => *loss,
+            Self::Random {
+                fraction,
+                drop_rate,
+            } => {
+                let amount = (nodes.len() as f64 * fraction).round() as usize;
+                let nodes: Vec<_> = nodes.choose_multiple(rng, amount).copied().collect();
+                Loss {
+                    egress: nodes.iter().map(|&node| (node, drop_rate)).collect(),
+                    ingress: nodes.iter().map(|&node| (node, drop_rate)).collect(),
+                    ..Loss::default()
+                }
+            }
+        }
+    }
+}
+
+// Parses loss specs of the form:
+//     file:PATH | random:FRACTION,DROP_RATE
+impl FromStr for LossSpec {
+    type Err = LossError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid_spec = || LossError::InvalidSpec(spec.to_string());
+        let (kind, args) = spec.split_once(':').ok_or_else(invalid_spec)?;
+        let args: Vec<_> = args.split(',').map(str::trim).collect();
+        match (kind, &args[..]) {
+            ("file", [path]) => Ok(Self::File(Box::new(Loss::from_file(path)?))),
+            ("random", [fraction, drop_rate]) => Ok(Self::Random {
+                fraction: parse_drop_rate(fraction)?,
+                drop_rate: parse_drop_rate(drop_rate)?,
+            }),
+            _ => Err(invalid_spec()),
+        }
+    }
+}
+
+// Parses Gilbert-Elliott model specs of the form:
+//     P,R[,H]
+// where H defaults to 1, i.e. all packets are dropped in the bad state.
+impl FromStr for GilbertElliott {
+    type Err = LossError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let args: Vec<_> = spec.split(',').map(str::trim).collect();
+        let (p, r, h) = match args[..] {
+            [p, r] => (p, r, "1"),
+            [p, r, h] => (p, r, h),
+            _ => return Err(LossError::InvalidSpec(spec.to_string())),
+        };
+        Ok(Self {
+            p: parse_drop_rate(p)?,
+            r: parse_drop_rate(r)?,
+            h: parse_drop_rate(h)?,
+            bad_links: Mutex::default(),
+        })
+    }
+}
+
+fn parse_drop_rate(drop_rate: &str) -> Result<f64, LossError> {
+    let rate = f64::from_str(drop_rate)?;
+    if !(0.0..=1.0).contains(&rate) {
+        return Err(LossError::InvalidSpec(drop_rate.to_string()));
+    }
+    Ok(rate)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng, std::iter::repeat_with};
+
+    #[test]
+    fn test_loss() {
+        let mut rng = ChaChaRng::from_seed([73u8; 32]);
+        let nodes: Vec<_> = repeat_with(Pubkey::new_unique).take(20).collect();
+        let spec = LossSpec::from_str("random:0.2,0.5").unwrap();
+        let loss = spec.resolve(&mut rng, &nodes);
+        let lossy = loss.nodes();
+        assert_eq!(lossy.len(), 4);
+        let (bad, good): (Vec<_>, Vec<_>) = nodes.iter().partition(|node| lossy.contains(node));
+        let mut get_drop_rate = |from, to| {
+            let num_drops = (0..10_000)
+                .filter(|_| loss.drop(&mut rng, from, to))
+                .count();
+            num_drops as f64 / 10_000.0
+        };
+        assert_eq!(get_drop_rate(good[0], good[1]), 0.0);
+        assert!((get_drop_rate(bad[0], good[1]) - 0.5).abs() < 0.02);
+        assert!((get_drop_rate(good[0], bad[1]) - 0.5).abs() < 0.02);
+        assert!((get_drop_rate(bad[0], bad[1]) - 0.75).abs() < 0.02);
+        for spec in ["random:0.2", "random:1.2,0.5", "file", "x:0.1,0.2"] {
+            assert!(LossSpec::from_str(spec).is_err(), "{spec}");
+        }
+    }
+
+    #[test]
+    fn test_gilbert_elliott() {
+        let mut rng = ChaChaRng::from_seed([79u8; 32]);
+        let burst = GilbertElliott::from_str("0.01,0.1").unwrap();
+        let loss = Loss::default().with_burst(burst);
+        let (from, to) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let drops: Vec<bool> = (0..100_000)
+            .map(|_| loss.drop(&mut rng, &from, &to))
+            .collect();
+        // Stationary probability of the bad state is p / (p + r).
+        let drop_rate = drops.iter().filter(|&&drop| drop).count() as f64 / 100_000.0;
+        assert!((drop_rate - 1.0 / 11.0).abs() < 0.01, "{drop_rate}");
+        // Mean burst length is 1 / r.
+        let num_bursts = drops.windows(2).filter(|w| !w[0] && w[1]).count();
+        let burst_length = drops.iter().filter(|&&drop| drop).count() as f64 / num_bursts as f64;
+        assert!((burst_length - 10.0).abs() < 1.0, "{burst_length}");
+        assert!(GilbertElliott::from_str("0.1").is_err());
+        assert!(GilbertElliott::from_str("0.1,0.2,1.5").is_err());
+    }
+}
@@ -0,0 +1,454 @@
+use {
+    crate::gossip::{get_crds_table, CrdsEntry, Node},
+    rand::{seq::SliceRandom, Rng},
+    solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
+    std::{
+        collections::HashSet,
+        fs::File,
+        io::{BufRead, BufReader},
+        num::{ParseFloatError, ParseIntError},
+        str::FromStr,
+        time::Duration,
+    },
+    thiserror::Error,
+};
+
+// A partition is considered healed once the fraction of up to date crds
+// entries across the partition boundary is within this ratio of the fraction
+// within either side.
+const RECONVERGENCE_THRESHOLD: f64 = 0.99;
+
+#[derive(Debug, Error)]
+pub enum PartitionError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid partition spec: {0}")]
+    InvalidSpec(String),
+    #[error("partition ends before it starts: {start:?}..{end:?}")]
+    InvalidWindow { start: Duration, end: Duration },
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+    #[error(transparent)]
+    ParsePubkeyError(#[from] ParsePubkeyError),
+}
+
+// Set of nodes which is cut off from the rest of the cluster during a time
+// window; packets crossing the partition boundary are dropped with the given
+// probability.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Partition {
+    pub nodes: HashSet<Pubkey>,
+    // Start and end of the partition relative to the simulation epoch.
+    pub start: Duration,
+    pub end: Duration,
+    pub drop_rate: f64,
+}
+
+// Partition as specified on the command line, before nodes are sampled and
+// rounds are converted to time.
+#[derive(Clone, Debug, PartialEq)]
+pub struct PartitionSpec {
+    nodes: PartitionNodes,
+    start: PartitionTime,
+    end: PartitionTime,
+    drop_rate: f64,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum PartitionNodes {
+    Pubkeys(HashSet<Pubkey>),
+    // Random fraction of the cluster nodes.
+    Random(f64),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum PartitionTime {
+    Duration(Duration),
+    // Number of gossip intervals.
+    Rounds(u32),
+}
+
+// Periodically samples how up to date crds tables are across each partition
+// boundary, in order to measure how long tables take to reconverge once the
+// partition heals.
+pub struct PartitionMonitor {
+    partitions: Vec<Partition>,
+    // For each sample and each partition, fraction of up to date crds
+    // entries across the partition boundary and within either side.
+    samples: Vec<(Duration, Vec<(/*across:*/ f64, /*within:*/ f64)>)>,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub struct PartitionReport {
+    // Min fraction of up to date crds entries across the partition boundary
+    // while the partition was active.
+    pub min_freshness: Option<f64>,
+    // Time from the end of the partition until crds tables reconverged, or
+    // None if they did not within the simulation.
+    pub reconvergence_time: Option<Duration>,
+}
+
+impl Partition {
+    pub(crate) fn is_active(&self, elapsed: Duration) -> bool {
+        self.start <= elapsed && elapsed < self.end
+    }
+
+    // Returns true if the packet crosses the partition boundary.
+    pub(crate) fn separates(&self, from: &Pubkey, to: &Pubkey) -> bool {
+        self.nodes.contains(from) != self.nodes.contains(to)
+    }
+}
+
+impl PartitionSpec {
+    // Samples partition nodes and converts rounds to time. Fails if the
+    // partition does not end after it starts, which from_str cannot check if
+    // start and end are in different units.
+    pub fn resolve<R: Rng>(
+        &self,
+        rng: &mut R,
+        nodes: &[Pubkey],
+        gossip_interval: Duration,
+    ) -> Result<Partition, PartitionError> {
+        let start = self.start.resolve(gossip_interval);
+        let end = self.end.resolve(gossip_interval);
+        if end <= start {
+            return Err(PartitionError::InvalidWindow { start, end });
+        }
+        let nodes = match &self.nodes {
+            PartitionNodes::Pubkeys(pubkeys) => pubkeys.clone(),
+            PartitionNodes::Random(fraction) => {
+                let amount = (nodes.len() as f64 * fraction).round() as usize;
+                nodes.choose_multiple(rng, amount).copied().collect()
+            }
+        };
+        Ok(Partition {
+            nodes,
+            start,
+            end,
+            drop_rate: self.drop_rate,
+        })
+    }
+}
+
+impl PartitionTime {
+    pub(crate) fn resolve(&self, gossip_interval: Duration) -> Duration {
+        match self {
+            Self::Duration(duration) => *duration,
+            Self::Rounds(rounds) => gossip_interval * *rounds,
+        }
+    }
+}
+
+impl PartitionMonitor {
+    pub fn new(partitions: Vec<Partition>) -> Self {
+        Self {
+            partitions,
+            samples: Vec::default(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.partitions.is_empty()
+    }
+
+    pub fn partitions(&self) -> &[Partition] {
+        &self.partitions
+    }
+
+    pub fn sample(&mut self, elapsed: Duration, nodes: &[&Node]) {
+        if self.partitions.is_empty() {
+            return;
+        }
+        let table = get_crds_table(nodes.iter().copied());
+        let sample = self
+            .partitions
+            .iter()
+            .map(|partition| {
+                // (number of up to date entries, number of entries)
+                let mut across = (0usize, 0usize);
+                let mut within = (0usize, 0usize);
+                for node in nodes {
+                    let node_table = node.table();
+                    let inside = partition.nodes.contains(&node.pubkey());
+                    for (key, ordinal) in &table {
+                        let acc = if partition.nodes.contains(&key.origin()) == inside {
+                            &mut within
+                        } else {
+                            &mut across
+                        };
+                        acc.1 += 1;
+                        if node_table.get(key).map(CrdsEntry::ordinal) == Some(*ordinal) {
+                            acc.0 += 1;
+                        }
+                    }
+                }
+                let ratio = |(num, den): (usize, usize)| {
+                    if den == 0 {
+                        1.0
+                    } else {
+                        num as f64 / den as f64
+                    }
+                };
+                (ratio(across), ratio(within))
+            })
+            .collect();
+        self.samples.push((elapsed, sample));
+    }
+
+    pub fn get_reports(&self) -> Vec<PartitionReport> {
+        self.partitions
+            .iter()
+            .enumerate()
+            .map(|(k, partition)| {
+                let min_freshness = self
+                    .samples
+                    .iter()
+                    .filter(|(elapsed, _)| partition.is_active(*elapsed))
+                    .map(|(_, sample)| sample[k].0)
+                    .min_by(f64::total_cmp);
+                let reconvergence_time = self
+                    .samples
+                    .iter()
+                    .filter(|(elapsed, _)| *elapsed >= partition.end)
+                    .find(|(_, sample)| {
+                        let (across, within) = sample[k];
+                        across >= within * RECONVERGENCE_THRESHOLD
+                    })
+                    .map(|(elapsed, _)| *elapsed - partition.end);
+                PartitionReport {
+                    min_freshness,
+                    reconvergence_time,
+                }
+            })
+            .collect()
+    }
+}
+
+// Parses partition specs of the form:
+//     NODES,START,END[,DROP_RATE]
+// where NODES is either of random:FRACTION or file:PATH with one pubkey per
+// line, START and END are in one of ms, s or r (gossip rounds) units, e.g.
+// 1500ms, 30s or 100r, END is after START, and DROP_RATE defaults to 1, i.e.
+// unreachable.
+impl FromStr for PartitionSpec {
+    type Err = PartitionError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid_spec = || PartitionError::InvalidSpec(spec.to_string());
+        let args: Vec<_> = spec.split(',').map(str::trim).collect();
+        let (nodes, start, end, drop_rate) = match args[..] {
+            [nodes, start, end] => (nodes, start, end, 1.0),
+            [nodes, start, end, drop_rate] => (nodes, start, end, f64::from_str(drop_rate)?),
+            _ => return Err(invalid_spec()),
+        };
+        let nodes = match nodes.split_once(':') {
+            Some(("random", fraction)) => {
+                let fraction = f64::from_str(fraction)?;
+                if !(0.0..=1.0).contains(&fraction) {
+                    return Err(invalid_spec());
+                }
+                PartitionNodes::Random(fraction)
+            }
+            Some(("file", path)) => PartitionNodes::Pubkeys(read_pubkeys(path)?),
+            _ => return Err(invalid_spec()),
+        };
+        let start = PartitionTime::from_str(start)?;
+        let end = PartitionTime::from_str(end)?;
+        if !(0.0..=1.0).contains(&drop_rate) {
+            return Err(invalid_spec());
+        }
+        // Start and end in different units are checked once resolved.
+        match (start, end) {
+            (PartitionTime::Duration(start), PartitionTime::Duration(end)) if end <= start => {
+                return Err(invalid_spec())
+            }
+            (PartitionTime::Rounds(start), PartitionTime::Rounds(end)) if end <= start => {
+                return Err(invalid_spec())
+            }
+            _ => (),
+        }
+        Ok(Self {
+            nodes,
+            start,
+            end,
+            drop_rate,
+        })
+    }
+}
+
+impl FromStr for PartitionTime {
+    type Err = PartitionError;
+
+    fn from_str(time: &str) -> Result<Self, Self::Err> {
+        if let Some(millis) = time.strip_suffix("ms") {
+            Ok(Self::Duration(Duration::from_millis(u64::from_str(
+                millis,
+            )?)))
+        } else if let Some(secs) = time.strip_suffix('s') {
+            Ok(Self::Duration(Duration::from_secs(u64::from_str(secs)?)))
+        } else if let Some(rounds) = time.strip_suffix('r') {
+            Ok(Self::Rounds(u32::from_str(rounds)?))
+        } else {
+            Err(PartitionError::InvalidSpec(time.to_string()))
+        }
+    }
+}
+
+fn read_pubkeys(path: &str) -> Result<HashSet<Pubkey>, PartitionError> {
+    let mut pubkeys = HashSet::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if !line.is_empty() && !line.starts_with('#') {
+            pubkeys.insert(Pubkey::from_str(line)?);
+        }
+    }
+    Ok(pubkeys)
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{gossip::Packet, latency::Latency, Delivery, Router},
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
+        std::{iter::repeat_with, sync::Arc, time::Instant},
+    };
+
+    #[test]
+    fn test_parse_partition_spec() {
+        let spec = PartitionSpec::from_str("random:0.25,10s,100r").unwrap();
+        assert_eq!(
+            spec,
+            PartitionSpec {
+                nodes: PartitionNodes::Random(0.25),
+                start: PartitionTime::Duration(Duration::from_secs(10)),
+                end: PartitionTime::Rounds(100),
+                drop_rate: 1.0,
+            }
+        );
+        let mut rng = ChaChaRng::from_seed([41u8; 32]);
+        let nodes: Vec<_> = repeat_with(Pubkey::new_unique).take(20).collect();
+        let partition = spec
+            .resolve(&mut rng, &nodes, Duration::from_millis(200))
+            .unwrap();
+        assert_eq!(partition.nodes.len(), 5);
+        assert!(partition.nodes.iter().all(|node| nodes.contains(node)));
+        assert_eq!(partition.start, Duration::from_secs(10));
+        assert_eq!(partition.end, Duration::from_secs(20));
+        assert!(partition.is_active(Duration::from_secs(15)));
+        assert!(!partition.is_active(Duration::from_secs(25)));
+        let spec = PartitionSpec::from_str("random:0.5, 1500ms, 90s, 0.9").unwrap();
+        let partition = spec
+            .resolve(&mut rng, &nodes, Duration::from_millis(200))
+            .unwrap();
+        assert!(partition.is_active(Duration::from_secs(30)));
+        assert!(!partition.is_active(Duration::from_secs(90)));
+        assert_eq!(partition.drop_rate, 0.9);
+        let (inside, outside): (Vec<_>, Vec<_>) = nodes
+            .iter()
+            .partition(|node| partition.nodes.contains(node));
+        assert!(partition.separates(inside[0], outside[0]));
+        assert!(!partition.separates(inside[0], inside[1]));
+        assert!(!partition.separates(outside[1], outside[0]));
+        for spec in [
+            "random:0.5,30s",
+            "random:1.5,30s,60s",
+            "random:0.5,30,60s",
+            "random:0.5,30s,60s,2",
+            "nodes:0.5,30s,60s",
+            "random:0.5,60s,30s",
+            "random:0.5,30s,30000ms",
+            "random:0.5,100r,100r",
+        ] {
+            assert!(PartitionSpec::from_str(spec).is_err(), "{spec}");
+        }
+        // 100 rounds of 200ms end before 30s.
+        let spec = PartitionSpec::from_str("random:0.5,30s,100r").unwrap();
+        assert!(spec
+            .resolve(&mut rng, &nodes, Duration::from_millis(200))
+            .is_err());
+        assert!(spec
+            .resolve(&mut rng, &nodes, Duration::from_millis(400))
+            .is_ok());
+    }
+
+    #[test]
+    fn test_router_partition() {
+        let mut rng = ChaChaRng::from_seed([43u8; 32]);
+        let nodes: Vec<_> = repeat_with(Pubkey::new_unique).take(3).collect();
+        let (senders, receivers): (Vec<_>, Vec<_>) = nodes
+            .iter()
+            .map(|&node| {
+                let (sender, receiver) = crossbeam_channel::unbounded();
+                ((node, sender), receiver)
+            })
+            .unzip();
+        let epoch = Instant::now();
+        let partition = Partition {
+            nodes: HashSet::from([nodes[0], nodes[1]]),
+            start: Duration::from_secs(10),
+            end: Duration::from_secs(20),
+            drop_rate: 1.0,
+        };
+        let router = Router::new(0.0, Latency::default(), senders)
+            .unwrap()
+            .with_partitions(epoch, vec![partition]);
+        let packet = Arc::new(Packet::Prune {
+            from: nodes[0],
+            origins: Vec::default(),
+        });
+        let mut send = |elapsed, from: usize, to: usize| {
+            let now = epoch + Duration::from_secs(elapsed);
+            router
+                .send(&mut rng, now, &nodes[from], &nodes[to], packet.clone())
+                .unwrap()
+        };
+        let delivered = Delivery::Delivered {
+            queueing_delay: Duration::ZERO,
+        };
+        // Packets across the partition boundary are dropped only while the
+        // partition is active.
+        assert_eq!(send(5, 0, 2), delivered);
+        assert_eq!(send(10, 0, 2), Delivery::Dropped);
+        assert_eq!(send(15, 2, 1), Delivery::Dropped);
+        assert_eq!(send(15, 0, 1), delivered);
+        assert_eq!(send(20, 2, 1), delivered);
+        assert_eq!(receivers[0].try_iter().count(), 0);
+        assert_eq!(receivers[1].try_iter().count(), 2);
+        assert_eq!(receivers[2].try_iter().count(), 1);
+    }
+
+    #[test]
+    fn test_partition_reports() {
+        let partition = Partition {
+            nodes: HashSet::from([Pubkey::new_unique()]),
+            start: Duration::from_secs(10),
+            end: Duration::from_secs(20),
+            drop_rate: 1.0,
+        };
+        let mut monitor = PartitionMonitor::new(vec![partition.clone(), partition]);
+        let secs = Duration::from_secs;
+        monitor.samples = vec![
+            (secs(5), vec![(0.2, 1.0), (0.9, 1.0)]),
+            (secs(10), vec![(0.95, 1.0), (0.9, 1.0)]),
+            (secs(15), vec![(0.6, 1.0), (0.5, 0.9)]),
+            (secs(20), vec![(0.7, 1.0), (0.8, 0.9)]),
+            (secs(25), vec![(0.995, 1.0), (0.85, 0.9)]),
+            (secs(30), vec![(1.0, 1.0), (0.895, 0.9)]),
+        ];
+        let reports = monitor.get_reports();
+        // Samples before the partition do not count towards min freshness.
+        assert_eq!(reports[0].min_freshness, Some(0.6));
+        assert_eq!(reports[0].reconvergence_time, Some(secs(5)));
+        assert_eq!(reports[1].min_freshness, Some(0.5));
+        assert_eq!(reports[1].reconvergence_time, Some(secs(10)));
+        monitor.samples.truncate(4);
+        let reports = monitor.get_reports();
+        assert_eq!(reports[0].reconvergence_time, None);
+        assert_eq!(reports[1].reconvergence_time, None);
+    }
+}
@@ -0,0 +1,160 @@
+use {
+    crate::push_active_set::get_stake_bucket,
+    solana_sdk::native_token::LAMPORTS_PER_SOL,
+    std::{num::ParseIntError, str::FromStr, sync::Arc},
+    thiserror::Error,
+};
+
+// Exponent of the stake-bucket weighting used by solana-gossip.
+pub const DEFAULT_BUCKET_EXPONENT: u32 = 2;
+
+// Resolution of log-stake weights, which are rounded to integers.
+const LOG_STAKE_SCALE: f64 = 1000.0;
+
+#[derive(Debug, Error)]
+pub enum PeerSamplerError {
+    #[error("invalid peer sampler: {0}")]
+    InvalidSpec(String),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+}
+
+// Weighs gossip peers when sampling them into the push active set.
+pub trait PeerSampler: Send + Sync {
+    // Returns the non-zero weight of a gossip peer for the active-set entry
+    // k, which holds nodes to push crds values to where
+    //     min stake of { this node, crds value owner }
+    // falls into stake bucket k. bucket is the stake bucket of the gossip
+    // peer, and stake its stake in lamports.
+    fn get_weight(&self, k: usize, bucket: usize, stake: u64) -> u64;
+}
+
+// weight <- (bucket + 1)^exponent where bucket is the stake bucket of
+//     min stake of { this node, crds value owner, gossip peer }
+// min stake of {...} is a proxy for how much we care about the link, and
+// tries to mirror similar logic on the receiving end when pruning incoming
+// links:
+// https://github.com/solana-labs/solana/blob/81394cf92/gossip/src/received_cache.rs#L100-L105
+pub struct StakeBucket(pub u32);
+
+// Weight is proportional to the gossip peer's stake, capped at the stakes in
+// bucket k as with StakeBucket.
+pub struct LinearStake;
+
+// Weight is proportional to the log of the gossip peer's stake, capped at the
+// stakes in bucket k as with StakeBucket.
+pub struct LogStake;
+
+// Samples gossip peers uniformly regardless of stake.
+pub struct Uniform;
+
+impl PeerSampler for StakeBucket {
+    fn get_weight(&self, k: usize, bucket: usize, _stake: u64) -> u64 {
+        let bucket = bucket.min(k) as u64;
+        bucket.saturating_add(1).saturating_pow(self.0)
+    }
+}
+
+impl PeerSampler for LinearStake {
+    fn get_weight(&self, k: usize, bucket: usize, stake: u64) -> u64 {
+        get_capped_stake(k, bucket, stake).saturating_add(1)
+    }
+}
+
+impl PeerSampler for LogStake {
+    fn get_weight(&self, k: usize, bucket: usize, stake: u64) -> u64 {
+        let stake = get_capped_stake(k, bucket, stake) as f64;
+        (stake.ln_1p() * LOG_STAKE_SCALE).round() as u64 + 1
+    }
+}
+
+impl PeerSampler for Uniform {
+    fn get_weight(&self, _k: usize, _bucket: usize, _stake: u64) -> u64 {
+        1
+    }
+}
+
+// Returns weights of gossip peers with the given stakes for the active-set
+// entry k, where stakes are mapped into num_buckets stake buckets.
+pub fn get_weights(
+    peer_sampler: &dyn PeerSampler,
+    k: usize,
+    num_buckets: usize,
+    stakes: &[u64],
+) -> Vec<u64> {
+    stakes
+        .iter()
+        .map(|&stake| {
+            let bucket = get_stake_bucket(Some(&stake), num_buckets);
+            peer_sampler.get_weight(k, bucket, stake)
+        })
+        .collect()
+}
+
+// Parses peer sampler specs of the form:
+//     bucket[:EXPONENT] | stake | log-stake | uniform
+// where EXPONENT defaults to 2, as in solana-gossip.
+pub fn parse_peer_sampler(spec: &str) -> Result<Arc<dyn PeerSampler>, PeerSamplerError> {
+    match spec.split_once(':') {
+        None => match spec {
+            "bucket" => Ok(Arc::new(StakeBucket(DEFAULT_BUCKET_EXPONENT))),
+            "stake" => Ok(Arc::new(LinearStake)),
+            "log-stake" => Ok(Arc::new(LogStake)),
+            "uniform" => Ok(Arc::new(Uniform)),
+            _ => Err(PeerSamplerError::InvalidSpec(spec.to_string())),
+        },
+        Some(("bucket", exponent)) => {
+            let exponent = u32::from_str(exponent.trim())?;
+            Ok(Arc::new(StakeBucket(exponent)))
+        }
+        Some(_) => Err(PeerSamplerError::InvalidSpec(spec.to_string())),
+    }
+}
+
+// Returns the stake in SOL capped at the largest stake in bucket k, so that
+// the stake reflects min stake of { this node, crds value owner, gossip peer }.
+fn get_capped_stake(k: usize, bucket: usize, stake: u64) -> u64 {
+    let stake = stake / LAMPORTS_PER_SOL;
+    if bucket <= k {
+        stake
+    } else {
+        stake.min((1u64 << k) - 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_peer_samplers() {
+        const NUM_BUCKETS: usize = 25;
+        const MAX_STAKE_BUCKET: usize = NUM_BUCKETS - 1;
+        let stakes = [0, 1, 3, 4, 1000, 1 << 30].map(|stake| stake * LAMPORTS_PER_SOL);
+        let get_weights = |spec, k| {
+            let sampler = parse_peer_sampler(spec).unwrap();
+            get_weights(&*sampler, k, NUM_BUCKETS, &stakes)
+        };
+        assert_eq!(
+            get_weights("bucket", MAX_STAKE_BUCKET),
+            [1, 4, 9, 16, 121, 625]
+        );
+        assert_eq!(get_weights("bucket", 2), [1, 4, 9, 9, 9, 9]);
+        assert_eq!(get_weights("bucket:1", 3), [1, 2, 3, 4, 4, 4]);
+        assert_eq!(get_weights("bucket:0", 3), [1; 6]);
+        assert_eq!(
+            get_weights("stake", MAX_STAKE_BUCKET),
+            [1, 2, 4, 5, 1001, (1 << 30) + 1]
+        );
+        assert_eq!(get_weights("stake", 2), [1, 2, 4, 4, 4, 4]);
+        assert_eq!(get_weights("stake", 0), [1; 6]);
+        assert_eq!(
+            get_weights("log-stake", 2),
+            [1, 694, 1387, 1387, 1387, 1387]
+        );
+        assert_eq!(get_weights("uniform", MAX_STAKE_BUCKET), [1; 6]);
+        for spec in ["", "buckets", "bucket:", "bucket:-1", "stake:2", "log"] {
+            assert!(parse_peer_sampler(spec).is_err(), "{spec}");
+        }
+    }
+}
@@ -0,0 +1,103 @@
+use {
+    rand::Rng,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        collections::HashMap,
+        time::{Duration, Instant},
+    },
+};
+
+// Tracks which peers have recently answered a ping, mirroring solana-gossip's
+// PingCache: a peer is verified if it responded with a matching pong within
+// the ttl, and pings to the same peer are rate limited.
+pub(crate) struct PingCache {
+    ttl: Duration,
+    rate_limit_delay: Duration,
+    // Time the last valid pong was received from each peer.
+    pongs: HashMap<Pubkey, Instant>,
+    // Token and time of the last ping sent to each peer.
+    pings: HashMap<Pubkey, (/*token:*/ u64, Instant)>,
+}
+
+impl PingCache {
+    pub(crate) fn new(ttl: Duration, rate_limit_delay: Duration) -> Self {
+        Self {
+            ttl,
+            rate_limit_delay,
+            pongs: HashMap::default(),
+            pings: HashMap::default(),
+        }
+    }
+
+    // Returns true if the node is verified, and the token of a ping to send to
+    // the node if any. Verified nodes are pinged again once their pong is
+    // older than ttl / 8, so that they do not expire while still reachable.
+    pub(crate) fn check<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        now: Instant,
+        node: &Pubkey,
+    ) -> (bool, Option<u64>) {
+        let age = self
+            .pongs
+            .get(node)
+            .map(|&pong| now.saturating_duration_since(pong))
+            .filter(|&age| age < self.ttl);
+        let should_ping = match age {
+            None => true,
+            Some(age) => age > self.ttl / 8,
+        };
+        let rate_limited = self.pings.get(node).map_or(false, |&(_, ping)| {
+            now.saturating_duration_since(ping) < self.rate_limit_delay
+        });
+        if !should_ping || rate_limited {
+            return (age.is_some(), None);
+        }
+        let token = rng.gen();
+        self.pings.insert(*node, (token, now));
+        (age.is_some(), Some(token))
+    }
+
+    // Records the pong if it answers the last ping sent to the node, returning
+    // true if so.
+    pub(crate) fn add(&mut self, now: Instant, node: &Pubkey, token: u64) -> bool {
+        match self.pings.get(node) {
+            Some(&(ping, _)) if ping == token => {
+                self.pongs.insert(*node, now);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng};
+
+    #[test]
+    fn test_ping_cache() {
+        let mut rng = ChaChaRng::from_seed([101u8; 32]);
+        let mut cache = PingCache::new(Duration::from_secs(80), Duration::from_secs(2));
+        let node = Pubkey::new_unique();
+        let now = Instant::now();
+        let (verified, token) = cache.check(&mut rng, now, &node);
+        assert!(!verified);
+        let token = token.unwrap();
+        // Rate limited.
+        assert_eq!(cache.check(&mut rng, now, &node), (false, None));
+        // Pong with a wrong token is ignored.
+        assert!(!cache.add(now, &node, token.wrapping_add(1)));
+        assert_eq!(cache.check(&mut rng, now, &node), (false, None));
+        assert!(cache.add(now, &node, token));
+        assert_eq!(cache.check(&mut rng, now, &node), (true, None));
+        // Pinged again once the pong is older than ttl / 8.
+        let later = now + Duration::from_secs(11);
+        let (verified, token) = cache.check(&mut rng, later, &node);
+        assert!(verified);
+        assert!(token.is_some());
+        // Expires after ttl.
+        let later = now + Duration::from_secs(80);
+        assert!(!cache.check(&mut rng, later, &node).0);
+    }
+}
@@ -0,0 +1,165 @@
+use std::time::Duration;
+
+// Log-linear histogram of durations at microsecond resolution. Values below
+// 64us are recorded exactly; above that each power of two is split into 32
+// buckets, bounding the relative error by 1/32.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Histogram(Vec</*count:*/ u64>);
+
+// Propagation metrics of crds values received by a node.
+#[derive(Clone, Debug, Default)]
+pub struct PropagationStats {
+    // Delay between a crds value being created at its origin and its first
+    // arrival at this node.
+    pub latency: Histogram,
+    // Number of crds values which first arrived after k hops, indexed by k.
+    pub num_hops: Vec<u64>,
+}
+
+impl Histogram {
+    const SUB_BUCKET_BITS: u32 = 5;
+    const NUM_SUB_BUCKETS: u64 = 1 << Self::SUB_BUCKET_BITS;
+
+    pub fn add(&mut self, value: Duration) {
+        let bucket = Self::get_bucket(value.as_micros() as u64);
+        if self.0.len() <= bucket {
+            self.0.resize(bucket + 1, 0);
+        }
+        self.0[bucket] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        if self.0.len() < other.0.len() {
+            self.0.resize(other.0.len(), 0);
+        }
+        for (count, other) in self.0.iter_mut().zip(&other.0) {
+            *count += other;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.0.iter().sum()
+    }
+
+    // Returns the upper bound of the largest recorded value.
+    pub fn max(&self) -> Option<Duration> {
+        let bucket = self.0.iter().rposition(|&count| count != 0)?;
+        Some(Duration::from_micros(Self::get_value(bucket + 1)))
+    }
+
+    // Returns the (lower bound of the) value below which the given fraction
+    // of recorded values fall.
+    pub fn percentile(&self, fraction: f64) -> Option<Duration> {
+        debug_assert!((0.0..=1.0).contains(&fraction));
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let target = ((count as f64 * fraction).ceil() as u64).clamp(1, count);
+        let mut acc = 0;
+        let bucket = self.0.iter().position(|&count| {
+            acc += count;
+            acc >= target
+        })?;
+        Some(Duration::from_micros(Self::get_value(bucket)))
+    }
+
+    fn get_bucket(value: u64) -> usize {
+        if value < 2 * Self::NUM_SUB_BUCKETS {
+            return value as usize;
+        }
+        let exp = u64::BITS - 1 - value.leading_zeros();
+        let sub_bucket = (value >> (exp - Self::SUB_BUCKET_BITS)) - Self::NUM_SUB_BUCKETS;
+        let bucket = u64::from(exp - Self::SUB_BUCKET_BITS) * Self::NUM_SUB_BUCKETS + sub_bucket;
+        bucket as usize + Self::NUM_SUB_BUCKETS as usize
+    }
+
+    // Inverse of get_bucket; returns the smallest value mapped to the bucket.
+    fn get_value(bucket: usize) -> u64 {
+        let bucket = bucket as u64;
+        if bucket < 2 * Self::NUM_SUB_BUCKETS {
+            return bucket;
+        }
+        let bucket = bucket - Self::NUM_SUB_BUCKETS;
+        let exp = bucket / Self::NUM_SUB_BUCKETS;
+        let sub_bucket = bucket % Self::NUM_SUB_BUCKETS;
+        (Self::NUM_SUB_BUCKETS + sub_bucket) << exp
+    }
+}
+
+impl PropagationStats {
+    pub(crate) fn record(&mut self, latency: Duration, num_hops: u8) {
+        self.latency.add(latency);
+        let num_hops = usize::from(num_hops);
+        if self.num_hops.len() <= num_hops {
+            self.num_hops.resize(num_hops + 1, 0);
+        }
+        self.num_hops[num_hops] += 1;
+    }
+
+    pub fn merge(&mut self, other: &Self) {
+        self.latency.merge(&other.latency);
+        if self.num_hops.len() < other.num_hops.len() {
+            self.num_hops.resize(other.num_hops.len(), 0);
+        }
+        for (count, other) in self.num_hops.iter_mut().zip(&other.num_hops) {
+            *count += other;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        rand::{Rng, SeedableRng},
+        rand_chacha::ChaChaRng,
+    };
+
+    #[test]
+    fn test_histogram_buckets() {
+        let mut bucket = 0;
+        for value in 0..1_000_000 {
+            let other = Histogram::get_bucket(value);
+            assert!(other == bucket || other == bucket + 1);
+            bucket = other;
+            let lower = Histogram::get_value(bucket);
+            let upper = Histogram::get_value(bucket + 1);
+            assert!(lower <= value && value < upper);
+            assert!((upper - lower) * 32 <= lower.max(32));
+        }
+        for value in [u64::MAX >> 1, u64::MAX - 1] {
+            let bucket = Histogram::get_bucket(value);
+            assert!(Histogram::get_value(bucket) <= value);
+        }
+    }
+
+    #[test]
+    fn test_histogram_percentile() {
+        let mut rng = ChaChaRng::from_seed([113u8; 32]);
+        let mut values: Vec<_> = (0..10_000)
+            .map(|_| Duration::from_micros(rng.gen_range(0, 2_000_000)))
+            .collect();
+        let mut histogram = Histogram::default();
+        let mut other = Histogram::default();
+        for (k, value) in values.iter().enumerate() {
+            if k % 3 == 0 {
+                histogram.add(*value);
+            } else {
+                other.add(*value);
+            }
+        }
+        histogram.merge(&other);
+        assert_eq!(histogram.count(), 10_000);
+        values.sort_unstable();
+        for (fraction, index) in [(0.5, 4_999), (0.9, 8_999), (0.99, 9_899)] {
+            let value = values[index];
+            let percentile = histogram.percentile(fraction).unwrap();
+            assert!(percentile <= value);
+            assert!(value.as_micros() - percentile.as_micros() <= value.as_micros() / 32);
+        }
+        assert!(histogram.max().unwrap() > values[9_999]);
+        assert_eq!(Histogram::default().percentile(0.5), None);
+        assert_eq!(Histogram::default().max(), None);
+    }
+}
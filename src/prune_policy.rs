@@ -0,0 +1,228 @@
+use {
+    itertools::Itertools,
+    rand::{Rng, RngCore},
+    solana_sdk::pubkey::Pubkey,
+    std::{cmp::Reverse, num::ParseFloatError, str::FromStr, sync::Arc},
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum PrunePolicyError {
+    #[error("invalid prune policy: {0}")]
+    InvalidSpec(String),
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+}
+
+// Node which has delivered crds values of an origin, as tracked by the
+// received-cache.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct IngressNode {
+    pub pubkey: Pubkey,
+    pub stake: u64,
+    // Number of timely deliveries, i.e. among the first few copies of a value.
+    pub score: usize,
+    // Sum of 1 / (1 + k) over deliveries, where k is the number of copies of
+    // the value received before; a proxy for the node's relative latency.
+    pub weighted_score: f64,
+}
+
+// Decides which ingress nodes of an origin are sent a prune message, once
+// enough values of the origin have been received.
+pub trait PrunePolicy: Send + Sync {
+    // Returns the nodes to prune, retaining at least min_ingress_nodes and,
+    // where the policy takes stake into account, min_ingress_stake.
+    fn prune(
+        &self,
+        rng: &mut dyn RngCore,
+        nodes: Vec<IngressNode>,
+        min_ingress_nodes: usize,
+        min_ingress_stake: u64,
+    ) -> Vec<Pubkey>;
+}
+
+// Ranks nodes by (score, stake), and retains nodes until both
+// min_ingress_nodes and min_ingress_stake are reached, as in solana-gossip.
+pub struct StakeThreshold;
+
+// Ranks nodes by score and retains only min_ingress_nodes, disregarding stake.
+pub struct ScoreOnly;
+
+// Same as StakeThreshold but ranks nodes by latency-weighted score, so that
+// nodes consistently delivering late copies are pruned first.
+pub struct LatencyWeighted;
+
+// Never prunes any node.
+pub struct NeverPrune;
+
+// Prunes each node which StakeThreshold would prune with the given
+// probability.
+pub struct Probabilistic(f64);
+
+impl PrunePolicy for StakeThreshold {
+    fn prune(
+        &self,
+        _rng: &mut dyn RngCore,
+        nodes: Vec<IngressNode>,
+        min_ingress_nodes: usize,
+        min_ingress_stake: u64,
+    ) -> Vec<Pubkey> {
+        let nodes = nodes
+            .into_iter()
+            .sorted_unstable_by_key(|node| (Reverse((node.score, node.stake)), node.pubkey));
+        prune_ranked(nodes, min_ingress_nodes, min_ingress_stake)
+    }
+}
+
+impl PrunePolicy for ScoreOnly {
+    fn prune(
+        &self,
+        _rng: &mut dyn RngCore,
+        nodes: Vec<IngressNode>,
+        min_ingress_nodes: usize,
+        _min_ingress_stake: u64,
+    ) -> Vec<Pubkey> {
+        nodes
+            .into_iter()
+            .sorted_unstable_by_key(|node| (Reverse(node.score), node.pubkey))
+            .skip(min_ingress_nodes)
+            .map(|node| node.pubkey)
+            .collect()
+    }
+}
+
+impl PrunePolicy for LatencyWeighted {
+    fn prune(
+        &self,
+        _rng: &mut dyn RngCore,
+        nodes: Vec<IngressNode>,
+        min_ingress_nodes: usize,
+        min_ingress_stake: u64,
+    ) -> Vec<Pubkey> {
+        let nodes = nodes.into_iter().sorted_unstable_by(|a, b| {
+            b.weighted_score
+                .total_cmp(&a.weighted_score)
+                .then(b.stake.cmp(&a.stake))
+                .then(a.pubkey.cmp(&b.pubkey))
+        });
+        prune_ranked(nodes, min_ingress_nodes, min_ingress_stake)
+    }
+}
+
+impl PrunePolicy for NeverPrune {
+    fn prune(
+        &self,
+        _rng: &mut dyn RngCore,
+        _nodes: Vec<IngressNode>,
+        _min_ingress_nodes: usize,
+        _min_ingress_stake: u64,
+    ) -> Vec<Pubkey> {
+        Vec::default()
+    }
+}
+
+impl PrunePolicy for Probabilistic {
+    fn prune(
+        &self,
+        rng: &mut dyn RngCore,
+        nodes: Vec<IngressNode>,
+        min_ingress_nodes: usize,
+        min_ingress_stake: u64,
+    ) -> Vec<Pubkey> {
+        let mut prunes = StakeThreshold.prune(rng, nodes, min_ingress_nodes, min_ingress_stake);
+        prunes.retain(|_| rng.gen_bool(self.0));
+        prunes
+    }
+}
+
+// Parses prune policy specs of the form:
+//     stake | score | latency | never | random:PROBABILITY
+pub fn parse_prune_policy(spec: &str) -> Result<Arc<dyn PrunePolicy>, PrunePolicyError> {
+    match spec.split_once(':') {
+        None => match spec {
+            "stake" => Ok(Arc::new(StakeThreshold)),
+            "score" => Ok(Arc::new(ScoreOnly)),
+            "latency" => Ok(Arc::new(LatencyWeighted)),
+            "never" => Ok(Arc::new(NeverPrune)),
+            _ => Err(PrunePolicyError::InvalidSpec(spec.to_string())),
+        },
+        Some(("random", probability)) => {
+            let probability = f64::from_str(probability.trim())?;
+            if !(0.0..=1.0).contains(&probability) {
+                return Err(PrunePolicyError::InvalidSpec(spec.to_string()));
+            }
+            Ok(Arc::new(Probabilistic(probability)))
+        }
+        Some(_) => Err(PrunePolicyError::InvalidSpec(spec.to_string())),
+    }
+}
+
+// Given nodes in the order they are retained, skips min_ingress_nodes and
+// then nodes until the cumulative stake of the skipped ones reaches
+// min_ingress_stake, and returns the rest.
+fn prune_ranked<I>(nodes: I, min_ingress_nodes: usize, min_ingress_stake: u64) -> Vec<Pubkey>
+where
+    I: IntoIterator<Item = IngressNode>,
+{
+    nodes
+        .into_iter()
+        .scan(0u64, |acc, node| {
+            let old = *acc;
+            *acc = acc.saturating_add(node.stake);
+            Some((node.pubkey, old))
+        })
+        .skip(min_ingress_nodes)
+        .skip_while(|&(_, stake)| stake < min_ingress_stake)
+        .map(|(node, _stake)| node)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
+        std::{collections::HashSet, iter::repeat_with},
+    };
+
+    #[test]
+    fn test_prune_policies() {
+        let mut rng = ChaChaRng::from_seed([103u8; 32]);
+        let pubkeys: Vec<_> = repeat_with(Pubkey::new_unique).take(4).collect();
+        // (score, weighted score, stake)
+        let nodes: Vec<_> = [(9, 2.0, 1), (7, 6.5, 8), (4, 5.0, 3), (1, 1.0, 9)]
+            .into_iter()
+            .zip(&pubkeys)
+            .map(|((score, weighted_score, stake), &pubkey)| IngressNode {
+                pubkey,
+                stake,
+                score,
+                weighted_score,
+            })
+            .collect();
+        let mut prune = |spec, min_ingress_nodes, min_ingress_stake| {
+            parse_prune_policy(spec)
+                .unwrap()
+                .prune(
+                    &mut rng,
+                    nodes.clone(),
+                    min_ingress_nodes,
+                    min_ingress_stake,
+                )
+                .into_iter()
+                .collect::<HashSet<_>>()
+        };
+        let set = |indices: &[usize]| indices.iter().map(|&k| pubkeys[k]).collect();
+        assert_eq!(prune("stake", 1, 0), set(&[1, 2, 3]));
+        assert_eq!(prune("stake", 1, 5), set(&[2, 3]));
+        assert_eq!(prune("score", 2, 100), set(&[2, 3]));
+        assert_eq!(prune("latency", 1, 9), set(&[0, 3]));
+        assert_eq!(prune("never", 0, 0), set(&[]));
+        assert_eq!(prune("random:1", 1, 0), set(&[1, 2, 3]));
+        assert_eq!(prune("random:0", 1, 0), set(&[]));
+        for spec in ["", "stakes", "random", "random:2", "random:x", "never:1"] {
+            assert!(parse_prune_policy(spec).is_err(), "{spec}");
+        }
+    }
+}
@@ -1,20 +1,23 @@
 use {
+    crate::peer_sampler::{get_weights, PeerSampler},
     indexmap::IndexMap,
     rand::Rng,
-    solana_bloom::bloom::{AtomicBloom, Bloom},
+    solana_bloom::bloom::{AtomicBloom, Bloom, BloomHashIndex},
     solana_gossip::weighted_shuffle::WeightedShuffle,
     solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey},
-    std::collections::HashMap,
+    std::{
+        collections::{HashMap, HashSet},
+        iter::repeat_with,
+    },
 };
 
-const NUM_PUSH_ACTIVE_SET_ENTRIES: usize = 25;
-
 // Each entry corresponds to a stake bucket for
 //     min stake of { this node, crds value owner }
 // The entry represents set of gossip nodes to actively
 // push to for crds values belonging to the bucket.
+// Entries are allocated on the first rotation.
 #[derive(Default)]
-pub(crate) struct PushActiveSet([PushActiveSetEntry; NUM_PUSH_ACTIVE_SET_ENTRIES]);
+pub(crate) struct PushActiveSet(Vec<PushActiveSetEntry>);
 
 // Keys are gossip nodes to push messages to.
 // Values are which origins the node has pruned.
@@ -36,7 +39,10 @@ impl PushActiveSet {
         stakes: &HashMap<Pubkey, u64>,
     ) -> impl Iterator<Item = &Pubkey> + 'a {
         let stake = stakes.get(pubkey).min(stakes.get(origin));
-        self.get_entry(stake).get_nodes(origin, should_force_push)
+        self.get_entry(stake)
+            .map(|entry| entry.get_nodes(origin, should_force_push))
+            .into_iter()
+            .flatten()
     }
 
     // Prunes origins for the given gossip node.
@@ -54,60 +60,70 @@ impl PushActiveSet {
                 continue;
             }
             let stake = stake.min(stakes.get(origin));
-            self.get_entry(stake).prune(node, origin)
+            if let Some(entry) = self.get_entry(stake) {
+                entry.prune(node, origin);
+            }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn rotate<R: Rng>(
         &mut self,
         rng: &mut R,
-        size: usize, // Number of nodes to retain in each active-set entry.
+        size: usize,        // Number of nodes to retain in each active-set entry.
+        num_entries: usize, // Number of stake buckets.
         cluster_size: usize,
         // Gossip nodes to be sampled for each push active set.
         nodes: &[Pubkey],
         stakes: &HashMap<Pubkey, u64>,
+        peer_sampler: &dyn PeerSampler,
+        bloom_false_rate: f64,
+        bloom_max_bits: usize,
     ) {
         let num_bloom_filter_items = cluster_size.max(Self::MIN_NUM_BLOOM_ITEMS);
         // Active set of nodes to push to are sampled from these gossip nodes,
-        // using sampling probabilities obtained from the stake bucket of each
-        // node.
-        let buckets: Vec<_> = nodes
+        // using sampling probabilities obtained from the stake of each node.
+        let stakes: Vec<u64> = nodes
             .iter()
-            .map(|node| get_stake_bucket(stakes.get(node)))
+            .map(|node| stakes.get(node).copied().unwrap_or_default())
             .collect();
+        self.0.resize_with(num_entries, PushActiveSetEntry::default);
         // (k, entry) represents push active set where the stake bucket of
         //     min stake of {this node, crds value owner}
         // is equal to `k`. The `entry` maintains set of gossip nodes to
         // actively push to for crds values belonging to this bucket.
         for (k, entry) in self.0.iter_mut().enumerate() {
-            let weights: Vec<u64> = buckets
-                .iter()
-                .map(|&bucket| {
-                    // bucket <- get_stake_bucket(min stake of {
-                    //  this node, crds value owner and gossip peer
-                    // })
-                    // weight <- (bucket + 1)^2
-                    // min stake of {...} is a proxy for how much we care about
-                    // the link, and tries to mirror similar logic on the
-                    // receiving end when pruning incoming links:
-                    // https://github.com/solana-labs/solana/blob/81394cf92/gossip/src/received_cache.rs#L100-L105
-                    let bucket = bucket.min(k) as u64;
-                    bucket.saturating_add(1).saturating_pow(2)
-                })
-                .collect();
-            entry.rotate(rng, size, num_bloom_filter_items, nodes, &weights);
+            let weights = get_weights(peer_sampler, k, num_entries, &stakes);
+            entry.rotate(
+                rng,
+                size,
+                num_bloom_filter_items,
+                bloom_false_rate,
+                bloom_max_bits,
+                nodes,
+                &weights,
+            );
         }
     }
 
-    fn get_entry(&self, stake: Option<&u64>) -> &PushActiveSetEntry {
-        &self.0[get_stake_bucket(stake)]
+    // Returns the number of distinct nodes across all active-set entries.
+    pub(crate) fn num_nodes(&self) -> usize {
+        self.nodes().collect::<HashSet<_>>().len()
+    }
+
+    // Returns nodes in each active-set entry, so a node appears once for
+    // every entry it occupies a slot in.
+    pub(crate) fn nodes(&self) -> impl Iterator<Item = &Pubkey> {
+        self.0.iter().flat_map(|entry| entry.0.keys())
+    }
+
+    // Returns None if the active set has not been rotated yet.
+    fn get_entry(&self, stake: Option<&u64>) -> Option<&PushActiveSetEntry> {
+        self.0.get(get_stake_bucket(stake, self.0.len()))
     }
 }
 
 impl PushActiveSetEntry {
-    const BLOOM_FALSE_RATE: f64 = 0.1;
-    const BLOOM_MAX_BITS: usize = 1024 * 8 * 4;
-
     fn get_nodes<'a>(
         &'a self,
         origin: &'a Pubkey,
@@ -132,32 +148,36 @@ impl PushActiveSetEntry {
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn rotate<R: Rng>(
         &mut self,
         rng: &mut R,
         size: usize, // Number of nodes to retain.
         num_bloom_filter_items: usize,
+        bloom_false_rate: f64,
+        bloom_max_bits: usize,
         nodes: &[Pubkey],
         weights: &[u64],
     ) {
         debug_assert_eq!(nodes.len(), weights.len());
         debug_assert!(weights.iter().all(|&weight| weight != 0u64));
-        let shuffle = WeightedShuffle::new("rotate-active-set", weights).shuffle(rng);
-        for node in shuffle.map(|k| &nodes[k]) {
-            // We intend to discard the oldest/first entry in the index-map.
-            if self.0.len() > size {
-                break;
-            }
-            if self.0.contains_key(node) {
-                continue;
-            }
-            let bloom = AtomicBloom::from(Bloom::random(
+        // We intend to discard the oldest/first entry in the index-map.
+        let num_new_nodes = (size + 1).saturating_sub(self.0.len());
+        let new_nodes: Vec<Pubkey> = WeightedShuffle::new("rotate-active-set", weights)
+            .shuffle(rng)
+            .map(|k| nodes[k])
+            .filter(|node| !self.0.contains_key(node))
+            .take(num_new_nodes)
+            .collect();
+        for node in new_nodes {
+            let bloom = AtomicBloom::from(make_bloom_filter(
+                rng,
                 num_bloom_filter_items,
-                Self::BLOOM_FALSE_RATE,
-                Self::BLOOM_MAX_BITS,
+                bloom_false_rate,
+                bloom_max_bits,
             ));
-            bloom.add(node);
-            self.0.insert(*node, bloom);
+            bloom.add(&node);
+            self.0.insert(node, bloom);
         }
         // Drop the oldest entry while preserving the ordering of others.
         while self.0.len() > size {
@@ -166,19 +186,53 @@ impl PushActiveSetEntry {
     }
 }
 
-// Maps stake to bucket index.
-fn get_stake_bucket(stake: Option<&u64>) -> usize {
+// Same as Bloom::random except that the hash keys are drawn from the given rng
+// (instead of thread_rng) so that simulations are reproducible from a seed.
+pub(crate) fn make_bloom_filter<T: BloomHashIndex, R: Rng>(
+    rng: &mut R,
+    num_items: usize,
+    false_rate: f64,
+    max_bits: usize,
+) -> Bloom<T> {
+    // https://hur.st/bloomfilter/
+    let num_items = num_items as f64;
+    let num_bits = (num_items * false_rate.ln()) / (1f64 / 2f64.powf(2f64.ln())).ln();
+    // The filter has at least one bit even if max_bits is zero.
+    let num_bits = (num_bits.ceil() as usize).clamp(1, max_bits.max(1));
+    let num_keys = if num_items == 0.0 {
+        0
+    } else {
+        1f64.max((num_bits as f64 / num_items * 2f64.ln()).round()) as usize
+    };
+    let keys = repeat_with(|| rng.gen()).take(num_keys).collect();
+    Bloom::new(num_bits, keys)
+}
+
+// Maps stake to bucket index, where the last of num_buckets buckets also
+// holds all larger stakes.
+pub(crate) fn get_stake_bucket(stake: Option<&u64>, num_buckets: usize) -> usize {
     let stake = stake.copied().unwrap_or_default() / LAMPORTS_PER_SOL;
     let bucket = u64::BITS - stake.leading_zeros();
-    (bucket as usize).min(NUM_PUSH_ACTIVE_SET_ENTRIES - 1)
+    (bucket as usize).min(num_buckets.saturating_sub(1))
 }
 
 #[cfg(test)]
 mod tests {
-    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng, std::iter::repeat_with};
+    use {
+        super::*,
+        crate::peer_sampler::{StakeBucket, DEFAULT_BUCKET_EXPONENT},
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
+    };
+
+    const NUM_PUSH_ACTIVE_SET_ENTRIES: usize = 25;
+    const BLOOM_FALSE_RATE: f64 = 0.1;
+    const BLOOM_MAX_BITS: usize = 1024 * 8 * 4;
 
     #[test]
     fn test_get_stake_bucket() {
+        let get_stake_bucket =
+            |stake: Option<&u64>| get_stake_bucket(stake, NUM_PUSH_ACTIVE_SET_ENTRIES);
         assert_eq!(get_stake_bucket(None), 0);
         let buckets = [0, 1, 2, 2, 3, 3, 3, 3, 4, 4, 4, 4, 4, 4, 4, 4, 5, 5];
         for (k, bucket) in buckets.into_iter().enumerate() {
@@ -200,6 +254,18 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_make_bloom_filter() {
+        let mut rng = ChaChaRng::from_seed([83u8; 32]);
+        let bloom: Bloom<Pubkey> = make_bloom_filter(&mut rng, 100, BLOOM_FALSE_RATE, 0);
+        assert_eq!(bloom.bits.len(), 1);
+        let bloom: Bloom<Pubkey> = make_bloom_filter(&mut rng, 100, BLOOM_FALSE_RATE, 64);
+        assert_eq!(bloom.bits.len(), 64);
+        let bloom: Bloom<Pubkey> =
+            make_bloom_filter(&mut rng, 100, BLOOM_FALSE_RATE, BLOOM_MAX_BITS);
+        assert_eq!(bloom.bits.len(), 480);
+    }
+
     #[test]
     fn test_push_active_set() {
         const CLUSTER_SIZE: usize = 117;
@@ -211,8 +277,27 @@ mod tests {
         let mut stakes: HashMap<_, _> = nodes.iter().copied().zip(stakes).collect();
         stakes.insert(pubkey, rng.gen_range(1, MAX_STAKE));
         let mut active_set = PushActiveSet::default();
-        assert!(active_set.0.iter().all(|entry| entry.0.is_empty()));
-        active_set.rotate(&mut rng, 5, CLUSTER_SIZE, &nodes, &stakes);
+        assert!(active_set.0.is_empty());
+        assert!(active_set
+            .get_nodes(&pubkey, &nodes[17], |_| false, &stakes)
+            .next()
+            .is_none());
+        let peer_sampler = StakeBucket(DEFAULT_BUCKET_EXPONENT);
+        let mut rotate = |active_set: &mut PushActiveSet, size| {
+            active_set.rotate(
+                &mut rng,
+                size,
+                NUM_PUSH_ACTIVE_SET_ENTRIES,
+                CLUSTER_SIZE,
+                &nodes,
+                &stakes,
+                &peer_sampler,
+                BLOOM_FALSE_RATE,
+                BLOOM_MAX_BITS,
+            )
+        };
+        rotate(&mut active_set, 5);
+        assert_eq!(active_set.0.len(), NUM_PUSH_ACTIVE_SET_ENTRIES);
         assert!(active_set.0.iter().all(|entry| entry.0.len() == 5));
         // Assert that for all entries, each filter already prunes the key.
         for entry in &active_set.0 {
@@ -224,37 +309,37 @@ mod tests {
         let origin = &nodes[17];
         assert!(active_set
             .get_nodes(&pubkey, origin, |_| false, &stakes)
-            .eq([13, 5, 18, 16, 0].into_iter().map(|k| &nodes[k])));
+            .eq([2, 6, 14, 13].into_iter().map(|k| &nodes[k])));
         assert!(active_set
             .get_nodes(&pubkey, other, |_| false, &stakes)
-            .eq([13, 18, 16, 0].into_iter().map(|k| &nodes[k])));
-        active_set.prune(&pubkey, &nodes[5], &[*origin], &stakes);
+            .eq([2, 6, 14, 17, 13].into_iter().map(|k| &nodes[k])));
+        active_set.prune(&pubkey, &nodes[6], &[*origin], &stakes);
         active_set.prune(&pubkey, &nodes[3], &[*origin], &stakes);
-        active_set.prune(&pubkey, &nodes[16], &[*origin], &stakes);
+        active_set.prune(&pubkey, &nodes[14], &[*origin], &stakes);
         assert!(active_set
             .get_nodes(&pubkey, origin, |_| false, &stakes)
-            .eq([13, 18, 0].into_iter().map(|k| &nodes[k])));
+            .eq([2, 13].into_iter().map(|k| &nodes[k])));
         assert!(active_set
             .get_nodes(&pubkey, other, |_| false, &stakes)
-            .eq([13, 18, 16, 0].into_iter().map(|k| &nodes[k])));
-        active_set.rotate(&mut rng, 7, CLUSTER_SIZE, &nodes, &stakes);
+            .eq([2, 6, 14, 17, 13].into_iter().map(|k| &nodes[k])));
+        rotate(&mut active_set, 7);
         assert!(active_set.0.iter().all(|entry| entry.0.len() == 7));
         assert!(active_set
             .get_nodes(&pubkey, origin, |_| false, &stakes)
-            .eq([18, 0, 7, 15, 11].into_iter().map(|k| &nodes[k])));
+            .eq([13, 4, 19, 3].into_iter().map(|k| &nodes[k])));
         assert!(active_set
             .get_nodes(&pubkey, other, |_| false, &stakes)
-            .eq([18, 16, 0, 7, 15, 11].into_iter().map(|k| &nodes[k])));
+            .eq([6, 14, 17, 13, 4, 19, 3].into_iter().map(|k| &nodes[k])));
         let origins = [*origin, *other];
-        active_set.prune(&pubkey, &nodes[18], &origins, &stakes);
-        active_set.prune(&pubkey, &nodes[0], &origins, &stakes);
-        active_set.prune(&pubkey, &nodes[15], &origins, &stakes);
+        active_set.prune(&pubkey, &nodes[6], &origins, &stakes);
+        active_set.prune(&pubkey, &nodes[13], &origins, &stakes);
+        active_set.prune(&pubkey, &nodes[19], &origins, &stakes);
         assert!(active_set
             .get_nodes(&pubkey, origin, |_| false, &stakes)
-            .eq([7, 11].into_iter().map(|k| &nodes[k])));
+            .eq([4, 3].into_iter().map(|k| &nodes[k])));
         assert!(active_set
             .get_nodes(&pubkey, other, |_| false, &stakes)
-            .eq([16, 7, 11].into_iter().map(|k| &nodes[k])));
+            .eq([14, 17, 4, 3].into_iter().map(|k| &nodes[k])));
     }
 
     #[test]
@@ -264,13 +349,18 @@ mod tests {
         let nodes: Vec<_> = repeat_with(Pubkey::new_unique).take(20).collect();
         let weights: Vec<_> = repeat_with(|| rng.gen_range(1, 1000)).take(20).collect();
         let mut entry = PushActiveSetEntry::default();
-        entry.rotate(
-            &mut rng,
-            5, // size
-            NUM_BLOOM_FILTER_ITEMS,
-            &nodes,
-            &weights,
-        );
+        let mut rotate = |entry: &mut PushActiveSetEntry, size| {
+            entry.rotate(
+                &mut rng,
+                size,
+                NUM_BLOOM_FILTER_ITEMS,
+                BLOOM_FALSE_RATE,
+                BLOOM_MAX_BITS,
+                &nodes,
+                &weights,
+            )
+        };
+        rotate(&mut entry, 5);
         assert_eq!(entry.0.len(), 5);
         let keys = [&nodes[16], &nodes[11], &nodes[17], &nodes[14], &nodes[5]];
         assert!(entry.0.keys().eq(keys));
@@ -304,16 +394,16 @@ mod tests {
             .into_iter()
             .filter(|&&node| node != nodes[11] && node != nodes[14])));
         // Assert that rotate adds new nodes.
-        entry.rotate(&mut rng, 5, NUM_BLOOM_FILTER_ITEMS, &nodes, &weights);
-        let keys = [&nodes[11], &nodes[17], &nodes[14], &nodes[5], &nodes[7]];
+        rotate(&mut entry, 5);
+        let keys = [&nodes[11], &nodes[17], &nodes[14], &nodes[5], &nodes[9]];
         assert!(entry.0.keys().eq(keys));
-        entry.rotate(&mut rng, 6, NUM_BLOOM_FILTER_ITEMS, &nodes, &weights);
+        rotate(&mut entry, 6);
         let keys = [
-            &nodes[17], &nodes[14], &nodes[5], &nodes[7], &nodes[1], &nodes[13],
+            &nodes[17], &nodes[14], &nodes[5], &nodes[9], &nodes[2], &nodes[18],
         ];
         assert!(entry.0.keys().eq(keys));
-        entry.rotate(&mut rng, 4, NUM_BLOOM_FILTER_ITEMS, &nodes, &weights);
-        let keys = [&nodes[5], &nodes[7], &nodes[1], &nodes[13]];
+        rotate(&mut entry, 4);
+        let keys = [&nodes[5], &nodes[9], &nodes[2], &nodes[18]];
         assert!(entry.0.keys().eq(keys));
     }
 }
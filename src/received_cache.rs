@@ -1,8 +1,9 @@
 use {
-    itertools::Itertools,
+    crate::prune_policy::{IngressNode, PrunePolicy},
     lru::LruCache,
+    rand::RngCore,
     solana_sdk::pubkey::Pubkey,
-    std::{cmp::Reverse, collections::HashMap},
+    std::collections::HashMap,
 };
 
 // For each origin, tracks which nodes have sent messages from that origin and
@@ -12,42 +13,67 @@ pub(crate) struct ReceivedCache(LruCache</*origin/owner:*/ Pubkey, ReceivedCache
 #[derive(Clone, Default)]
 struct ReceivedCacheEntry {
     nodes: HashMap<Pubkey, /*score:*/ usize>,
+    // Latency-weighted score of each node in nodes; see IngressNode.
+    weighted_scores: HashMap<Pubkey, f64>,
     num_upserts: usize,
 }
 
 impl ReceivedCache {
-    // Minimum number of upserts before a cache entry can be pruned.
-    const MIN_NUM_UPSERTS: usize = 20;
-
     pub(crate) fn new(capacity: usize) -> Self {
         Self(LruCache::new(capacity))
     }
 
-    pub(crate) fn record(&mut self, origin: Pubkey, node: Pubkey, num_dups: usize) {
+    pub(crate) fn record(
+        &mut self,
+        origin: Pubkey,
+        node: Pubkey,
+        num_dups: usize,
+        // Threshold for the number of duplicates before which a message
+        // is counted as timely towards node's score.
+        num_dups_threshold: usize,
+        // Limit how big each entry can get if it is spammed
+        // with old messages with random pubkeys.
+        entry_capacity: usize,
+    ) {
         match self.0.get_mut(&origin) {
-            Some(entry) => entry.record(node, num_dups),
+            Some(entry) => entry.record(node, num_dups, num_dups_threshold, entry_capacity),
             None => {
                 let mut entry = ReceivedCacheEntry::default();
-                entry.record(node, num_dups);
+                entry.record(node, num_dups, num_dups_threshold, entry_capacity);
                 self.0.put(origin, entry);
             }
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub(crate) fn prune(
         &mut self,
+        rng: &mut dyn RngCore,
         pubkey: &Pubkey, // This node.
         origin: Pubkey,  // CRDS value owner.
+        policy: &dyn PrunePolicy,
+        // Minimum number of upserts before a cache entry can be pruned.
+        min_num_upserts: usize,
         stake_threshold: f64,
         min_ingress_nodes: usize,
         stakes: &HashMap<Pubkey, u64>,
     ) -> impl Iterator<Item = Pubkey> {
         match self.0.peek_mut(&origin) {
             None => None,
-            Some(entry) if entry.num_upserts < Self::MIN_NUM_UPSERTS => None,
+            Some(entry) if entry.num_upserts < min_num_upserts => None,
             Some(entry) => Some(
                 std::mem::take(entry)
-                    .prune(pubkey, &origin, stake_threshold, min_ingress_nodes, stakes)
+                    .prune(
+                        rng,
+                        pubkey,
+                        &origin,
+                        policy,
+                        min_num_upserts,
+                        stake_threshold,
+                        min_ingress_nodes,
+                        stakes,
+                    )
+                    .into_iter()
                     .filter(move |node| node != &origin),
             ),
         }
@@ -66,61 +92,63 @@ impl ReceivedCache {
 }
 
 impl ReceivedCacheEntry {
-    // Limit how big the cache can get if it is spammed
-    // with old messages with random pubkeys.
-    const CAPACITY: usize = 50;
-    // Threshold for the number of duplicates before which a message
-    // is counted as timely towards node's score.
-    const NUM_DUPS_THRESHOLD: usize = 2;
-
-    fn record(&mut self, node: Pubkey, num_dups: usize) {
+    fn record(
+        &mut self,
+        node: Pubkey,
+        num_dups: usize,
+        num_dups_threshold: usize,
+        capacity: usize,
+    ) {
         if num_dups == 0 {
             self.num_upserts = self.num_upserts.saturating_add(1);
         }
         // If the message has been timely enough increment node's score.
-        if num_dups < Self::NUM_DUPS_THRESHOLD {
+        if num_dups < num_dups_threshold {
             let score = self.nodes.entry(node).or_default();
             *score = score.saturating_add(1);
-        } else if self.nodes.len() < Self::CAPACITY {
+        } else if self.nodes.len() < capacity {
             // Ensure that node is inserted into the cache for later pruning.
             // This intentionally does not negatively impact node's score, in
             // order to prevent replayed messages with spoofed addresses force
             // pruning a good node.
             let _ = self.nodes.entry(node).or_default();
+        } else if !self.nodes.contains_key(&node) {
+            return;
         }
+        *self.weighted_scores.entry(node).or_default() += 1.0 / (1.0 + num_dups as f64);
     }
 
+    #[allow(clippy::too_many_arguments)]
     fn prune(
         self,
+        rng: &mut dyn RngCore,
         pubkey: &Pubkey, // This node.
         origin: &Pubkey, // CRDS value owner.
+        policy: &dyn PrunePolicy,
+        min_num_upserts: usize,
         stake_threshold: f64,
         min_ingress_nodes: usize,
         stakes: &HashMap<Pubkey, u64>,
-    ) -> impl Iterator<Item = Pubkey> {
+    ) -> Vec<Pubkey> {
         debug_assert!((0.0..=1.0).contains(&stake_threshold));
-        debug_assert!(self.num_upserts >= ReceivedCache::MIN_NUM_UPSERTS);
+        debug_assert!(self.num_upserts >= min_num_upserts);
         // Enforce a minimum aggregate ingress stake; see:
         // https://github.com/solana-labs/solana/issues/3214
         let min_ingress_stake = {
             let stake = stakes.get(pubkey).min(stakes.get(origin));
             (stake.copied().unwrap_or_default() as f64 * stake_threshold) as u64
         };
-        self.nodes
+        let nodes = self
+            .nodes
             .into_iter()
-            .map(|(node, score)| {
-                let stake = stakes.get(&node).copied().unwrap_or_default();
-                (node, score, stake)
-            })
-            .sorted_unstable_by_key(|&(_, score, stake)| Reverse((score, stake)))
-            .scan(0u64, |acc, (node, _score, stake)| {
-                let old = *acc;
-                *acc = acc.saturating_add(stake);
-                Some((node, old))
+            .map(|(node, score)| IngressNode {
+                pubkey: node,
+                stake: stakes.get(&node).copied().unwrap_or_default(),
+                score,
+                weighted_score: self.weighted_scores.get(&node).copied().unwrap_or_default(),
             })
-            .skip(min_ingress_nodes)
-            .skip_while(move |&(_, stake)| stake < min_ingress_stake)
-            .map(|(node, _stake)| node)
+            .collect();
+        policy.prune(rng, nodes, min_ingress_nodes, min_ingress_stake)
     }
 }
 
@@ -128,9 +156,16 @@ impl ReceivedCacheEntry {
 mod tests {
     use {
         super::*,
+        crate::prune_policy::StakeThreshold,
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
         std::{collections::HashSet, iter::repeat_with},
     };
 
+    const MIN_NUM_UPSERTS: usize = 20;
+    const ENTRY_CAPACITY: usize = 50;
+    const NUM_DUPS_THRESHOLD: usize = 2;
+
     #[test]
     fn test_received_cache() {
         let mut cache = ReceivedCache::new(/*capacity:*/ 100);
@@ -149,7 +184,7 @@ mod tests {
         for (node, records) in nodes.iter().zip(records) {
             for (num_dups, k) in records.into_iter().enumerate() {
                 for _ in 0..k {
-                    cache.record(origin, *node, num_dups);
+                    cache.record(origin, *node, num_dups, NUM_DUPS_THRESHOLD, ENTRY_CAPACITY);
                 }
             }
         }
@@ -175,18 +210,37 @@ mod tests {
         ]
         .into_iter()
         .collect();
+        let mut rng = ChaChaRng::from_seed([107u8; 32]);
         let prunes: HashSet<Pubkey> = [nodes[0], nodes[2], nodes[3]].into_iter().collect();
         assert_eq!(
             cache
                 .mock_clone()
-                .prune(&pubkey, origin, 0.5, 2, &stakes)
+                .prune(
+                    &mut rng,
+                    &pubkey,
+                    origin,
+                    &StakeThreshold,
+                    MIN_NUM_UPSERTS,
+                    0.5,
+                    2,
+                    &stakes
+                )
                 .collect::<HashSet<_>>(),
             prunes
         );
         let prunes: HashSet<Pubkey> = [nodes[0], nodes[2]].into_iter().collect();
         assert_eq!(
             cache
-                .prune(&pubkey, origin, 1.0, 0, &stakes)
+                .prune(
+                    &mut rng,
+                    &pubkey,
+                    origin,
+                    &StakeThreshold,
+                    MIN_NUM_UPSERTS,
+                    1.0,
+                    0,
+                    &stakes
+                )
                 .collect::<HashSet<_>>(),
             prunes
         );
@@ -1,14 +1,22 @@
 use {
     clap::{crate_description, crate_name, App, Arg},
-    cluster_mocks::{gossip::make_gossip_cluster, API_MAINNET_BETA},
+    cluster_mocks::{
+        gossip::{load_cluster_nodes, ClusterSource},
+        peer_sampler::{get_weights, parse_peer_sampler, PeerSampler},
+        snapshot::write_snapshot,
+        synthetic::parse_unstaked_fraction,
+        API_MAINNET_BETA,
+    },
+    itertools::{izip, Itertools},
     log::info,
-    rand::Rng,
-    solana_client::rpc_client::RpcClient,
+    rand::{Rng, SeedableRng},
+    rand_chacha::ChaChaRng,
     solana_gossip::weighted_shuffle::WeightedShuffle,
     solana_sdk::pubkey::Pubkey,
     std::{
         cmp::Reverse,
         collections::HashMap,
+        path::PathBuf,
         time::{Duration, Instant},
     },
 };
@@ -16,26 +24,36 @@ use {
 #[derive(Debug)]
 struct Config {
     gossip_push_fanout: usize,
+    num_push_active_set_entries: usize,
     num_rounds: usize,
     round_delay: Duration,
+    seed: u64,
 }
 
-fn get_weight(pubkey: &Pubkey, stakes: &HashMap<Pubkey, u64>) -> u64 {
-    // TODO: Needs to min with this node's stake!
-    // TODO: no need to run sim here anymore!
-    let stake = stakes.get(pubkey).copied().unwrap_or_default();
-    let weight = u64::BITS - stake.leading_zeros();
-    u64::from(weight).saturating_add(1).saturating_pow(2)
-}
-
-fn run_sample_peers<R: Rng>(rng: &mut R, config: &Config, stakes: &HashMap<Pubkey, u64>) {
+fn run_sample_peers<R: Rng>(
+    rng: &mut R,
+    config: &Config,
+    peer_sampler: &dyn PeerSampler,
+    stakes: &HashMap<Pubkey, u64>,
+) {
     let mut now = Instant::now();
     let mut hits = HashMap::<Pubkey, usize>::with_capacity(stakes.len());
+    // Sort nodes so that the sampling is reproducible from the rng seed.
+    let (nodes, node_stakes): (Vec<_>, Vec<_>) = stakes
+        .iter()
+        .map(|(&pubkey, &stake)| (pubkey, stake))
+        .sorted_unstable()
+        .unzip();
+    // Weights of the push active-set entry of the highest stake bucket, i.e.
+    // as if this node and the crds value owner are both heavily staked.
+    // TODO: no need to run sim here anymore!
+    let weights = get_weights(
+        peer_sampler,
+        config.num_push_active_set_entries - 1, // k
+        config.num_push_active_set_entries,
+        &node_stakes,
+    );
     for _ in 0..config.num_rounds {
-        let (nodes, weights): (Vec<_>, Vec<_>) = stakes
-            .keys()
-            .map(|pubkey| (*pubkey, get_weight(pubkey, stakes)))
-            .unzip();
         let shuffle = WeightedShuffle::new("run-sample-peers", &weights).shuffle(rng);
         for k in shuffle.take(config.gossip_push_fanout) {
             let node = nodes[k];
@@ -44,21 +62,20 @@ fn run_sample_peers<R: Rng>(rng: &mut R, config: &Config, stakes: &HashMap<Pubke
         now += config.round_delay;
     }
     let active_stake: u64 = stakes.values().sum();
-    let mut hits: Vec<_> = stakes
-        .iter()
-        .map(|(&pubkey, &stake)| {
+    let mut hits: Vec<_> = izip!(nodes, node_stakes, weights)
+        .map(|(pubkey, stake, weight)| {
             let hits = hits.get(&pubkey).copied().unwrap_or_default();
-            (pubkey, stake, hits)
+            (pubkey, stake, weight, hits)
         })
         .collect();
-    hits.sort_unstable_by_key(|(_pubkey, stake, _hits)| Reverse(*stake));
+    hits.sort_unstable_by_key(|(_pubkey, stake, _weight, _hits)| Reverse(*stake));
     println!("node     | stake  | stake | hits");
-    for (pubkey, stake, hits) in hits {
+    for (pubkey, stake, weight, hits) in hits {
         println!(
             "{} | {:.3}% | {:5.2} | {:5}",
             &format!("{pubkey}")[..8],
             stake as f64 * 100.0 / active_stake as f64,
-            get_weight(&pubkey, stakes),
+            weight,
             hits
         );
     }
@@ -80,6 +97,47 @@ fn main() {
                 .default_value(API_MAINNET_BETA)
                 .help("solana's json rpc url"),
         )
+        .arg(
+            Arg::with_name("snapshot")
+                .long("snapshot")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("load cluster nodes from a snapshot file instead of json rpc"),
+        )
+        .arg(
+            Arg::with_name("dump_snapshot")
+                .long("dump-snapshot")
+                .value_name("PATH")
+                .takes_value(true)
+                .help("write cluster nodes to a snapshot file"),
+        )
+        .arg(
+            Arg::with_name("synthetic")
+                .long("synthetic")
+                .value_name("NUM_NODES")
+                .takes_value(true)
+                .conflicts_with("snapshot")
+                .help("generate a synthetic cluster with the given number of nodes"),
+        )
+        .arg(
+            Arg::with_name("stake_distribution")
+                .long("stake-distribution")
+                .value_name("SPEC")
+                .takes_value(true)
+                .default_value("pareto:1000,1.2")
+                .help(
+                    "stake distribution of synthetic cluster in SOL: uniform:MIN,MAX \
+                    | pareto:MIN,ALPHA | zipf:MAX,EXPONENT | snapshot:PATH",
+                ),
+        )
+        .arg(
+            Arg::with_name("unstaked_fraction")
+                .long("unstaked-fraction")
+                .takes_value(true)
+                .default_value("0.0")
+                .validator(parse_unstaked_fraction)
+                .help("fraction of unstaked nodes in synthetic cluster"),
+        )
         .arg(
             Arg::with_name("gossip_push_fanout")
                 .long("gossip-push-fanout")
@@ -87,6 +145,26 @@ fn main() {
                 .default_value("5")
                 .help("gossip push fanout"),
         )
+        .arg(
+            Arg::with_name("peer_sampler")
+                .long("peer-sampler")
+                .value_name("SAMPLER")
+                .takes_value(true)
+                .default_value("bucket")
+                .validator(parse_peer_sampler)
+                .help(
+                    "how gossip peers are weighted: bucket[:EXPONENT] \
+                    ((stake bucket + 1)^EXPONENT, 2 in solana-gossip), \
+                    stake, log-stake, or uniform",
+                ),
+        )
+        .arg(
+            Arg::with_name("num_push_active_set_entries")
+                .long("num-push-active-set-entries")
+                .takes_value(true)
+                .default_value("25")
+                .help("number of stake buckets, each with its own push active-set entry"),
+        )
         .arg(
             Arg::with_name("num_rounds")
                 .long("num-rounds")
@@ -101,22 +179,48 @@ fn main() {
                 .default_value("200")
                 .help("delay between rounds [ms]"),
         )
+        .arg(
+            Arg::with_name("seed")
+                .long("seed")
+                .takes_value(true)
+                .help("rng seed to reproduce a run; random if not specified"),
+        )
         .get_matches();
     let config = Config {
         gossip_push_fanout: matches.value_of_t_or_exit("gossip_push_fanout"),
+        num_push_active_set_entries: matches.value_of_t_or_exit("num_push_active_set_entries"),
         num_rounds: matches.value_of_t_or_exit("num_rounds"),
         round_delay: Duration::from_millis(matches.value_of_t_or_exit("round_delay")),
+        seed: matches
+            .value_of_t("seed")
+            .unwrap_or_else(|_| rand::thread_rng().gen()),
     };
     info!("config: {:#?}", config);
-    let json_rpc_url =
-        cluster_mocks::get_json_rpc_url(matches.value_of("json_rpc_url").unwrap_or_default());
-    info!("json_rpc_url: {}", json_rpc_url);
-    let rpc_client = RpcClient::new(json_rpc_url);
-    let stakes: HashMap<Pubkey, /*stake:*/ u64> = make_gossip_cluster(&rpc_client)
-        .unwrap()
+    assert!(config.num_push_active_set_entries > 0);
+    let peer_sampler = parse_peer_sampler(matches.value_of("peer_sampler").unwrap()).unwrap();
+    let mut rng = ChaChaRng::seed_from_u64(config.seed);
+    let source = match (
+        matches.value_of("snapshot"),
+        matches.is_present("synthetic"),
+    ) {
+        (Some(path), _) => ClusterSource::Snapshot(PathBuf::from(path)),
+        (None, true) => ClusterSource::Synthetic {
+            num_nodes: matches.value_of_t_or_exit("synthetic"),
+            distribution: matches.value_of_t_or_exit("stake_distribution"),
+            unstaked_fraction: matches.value_of_t_or_exit("unstaked_fraction"),
+        },
+        (None, false) => ClusterSource::JsonRpc(
+            cluster_mocks::get_json_rpc_url(matches.value_of("json_rpc_url").unwrap_or_default())
+                .to_string(),
+        ),
+    };
+    let nodes = load_cluster_nodes(&mut rng, &source).unwrap();
+    if let Some(path) = matches.value_of("dump_snapshot") {
+        write_snapshot(path, &nodes).unwrap();
+    }
+    let stakes: HashMap<Pubkey, /*stake:*/ u64> = nodes
         .into_iter()
-        .map(|(node, _sender)| (node.pubkey(), node.stake()))
+        .map(|node| (node.pubkey, node.stake))
         .collect();
-    let mut rng = rand::thread_rng();
-    run_sample_peers(&mut rng, &config, &stakes);
+    run_sample_peers(&mut rng, &config, &*peer_sampler, &stakes);
 }
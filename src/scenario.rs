@@ -0,0 +1,189 @@
+use {
+    serde::{Deserialize, Serialize},
+    std::{collections::BTreeMap, fs::File, io::Write, path::Path},
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum ScenarioError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid scenario value: {0}")]
+    InvalidValue(String),
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+    #[error(transparent)]
+    TomlDeError(#[from] toml::de::Error),
+    #[error(transparent)]
+    TomlSerError(#[from] toml::ser::Error),
+}
+
+// Simulation scenario as command line arguments keyed by long flag, e.g.:
+//     gossip_push_fanout = 6
+//     partition = ["random:0.5,60s,120s", "random:0.1,30s,90s,0.5"]
+//     virtual_time = true
+// Arguments are parsed and validated by the binary exactly as if given on the
+// command line, so that individual flags can override the scenario file and
+// the effective scenario of a run can be written back and reloaded.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+#[serde(transparent)]
+pub struct Scenario(BTreeMap<String, ScenarioValue>);
+
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(untagged)]
+pub enum ScenarioValue {
+    Flag(bool),
+    Integer(u64),
+    Float(f64),
+    Value(String),
+    Values(Vec<ScenarioValue>),
+}
+
+impl Scenario {
+    pub fn insert(&mut self, name: &str, value: ScenarioValue) {
+        self.0.insert(name.replace('-', "_"), value);
+    }
+
+    // Retains only arguments for which the predicate on the name is true.
+    pub fn retain<F: FnMut(&str) -> bool>(&mut self, mut pred: F) {
+        self.0.retain(|name, _| pred(name));
+    }
+
+    // Returns the command line arguments equivalent to the scenario. Flags set
+    // to false are omitted.
+    pub fn to_args(&self) -> Result<Vec<String>, ScenarioError> {
+        let mut args = Vec::default();
+        for (name, value) in &self.0 {
+            let flag = format!("--{}", name.replace('_', "-"));
+            match value {
+                ScenarioValue::Flag(false) => (),
+                ScenarioValue::Flag(true) => args.push(flag),
+                ScenarioValue::Values(values) => {
+                    for value in values {
+                        args.push(flag.clone());
+                        args.push(value.to_arg()?);
+                    }
+                }
+                value => {
+                    args.push(flag);
+                    args.push(value.to_arg()?);
+                }
+            }
+        }
+        Ok(args)
+    }
+}
+
+impl ScenarioValue {
+    fn to_arg(&self) -> Result<String, ScenarioError> {
+        match self {
+            Self::Integer(value) => Ok(value.to_string()),
+            Self::Float(value) => Ok(value.to_string()),
+            Self::Value(value) => Ok(value.clone()),
+            Self::Flag(_) | Self::Values(_) => {
+                Err(ScenarioError::InvalidValue(format!("{self:?}")))
+            }
+        }
+    }
+}
+
+// Reads the scenario from a json file if the path ends with .json, and from a
+// toml file otherwise. Argument names may be given in either snake_case or
+// kebab-case.
+pub fn read_scenario<P: AsRef<Path>>(path: P) -> Result<Scenario, ScenarioError> {
+    let path = path.as_ref();
+    let data = std::fs::read_to_string(path)?;
+    let scenario: Scenario = if is_json(path) {
+        serde_json::from_str(&data)?
+    } else {
+        toml::from_str(&data)?
+    };
+    let mut out = Scenario::default();
+    for (name, value) in scenario.0 {
+        out.insert(&name, value);
+    }
+    Ok(out)
+}
+
+// Writes the scenario in the same format read_scenario expects given the
+// path.
+pub fn write_scenario<P: AsRef<Path>>(path: P, scenario: &Scenario) -> Result<(), ScenarioError> {
+    let path = path.as_ref();
+    let data = if is_json(path) {
+        serde_json::to_string_pretty(scenario)?
+    } else {
+        toml::to_string(scenario)?
+    };
+    let mut file = File::create(path)?;
+    writeln!(file, "{}", data.trim_end())?;
+    Ok(())
+}
+
+fn is_json(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.eq_ignore_ascii_case("json"))
+        .unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{latency::Latency, partition::PartitionSpec},
+        std::str::FromStr,
+    };
+
+    #[test]
+    fn test_scenario_round_trip() {
+        let path = std::env::temp_dir().join(format!(
+            "cluster-mocks-scenario-{}.toml",
+            std::process::id()
+        ));
+        std::fs::write(
+            &path,
+            "gossip-push-fanout = 6\n\
+            packet_drop_rate = 0.05\n\
+            virtual_time = true\n\
+            dump_churn = false\n\
+            latency = \"uniform:10,300\"\n\
+            partition = [\"random:0.5,60s,120s\", \"random:0.1,150r,450r,0.5\"]\n",
+        )
+        .unwrap();
+        let mut scenario = read_scenario(&path).unwrap();
+        assert_eq!(
+            scenario.to_args().unwrap(),
+            [
+                "--gossip-push-fanout",
+                "6",
+                "--latency",
+                "uniform:10,300",
+                "--packet-drop-rate",
+                "0.05",
+                "--partition",
+                "random:0.5,60s,120s",
+                "--partition",
+                "random:0.1,150r,450r,0.5",
+                "--virtual-time",
+            ]
+        );
+        // Values are passed through as is, and so parse as on the command line.
+        let args = scenario.to_args().unwrap();
+        assert!(Latency::from_str(&args[3]).is_ok());
+        assert!(PartitionSpec::from_str(&args[7]).is_ok());
+        assert!(PartitionSpec::from_str(&args[9]).is_ok());
+        scenario.retain(|name| name != "partition");
+        scenario.insert("seed", ScenarioValue::Value(u64::MAX.to_string()));
+        for extension in ["toml", "json"] {
+            let path = path.with_extension(extension);
+            write_scenario(&path, &scenario).unwrap();
+            let other = read_scenario(&path);
+            std::fs::remove_file(&path).unwrap();
+            assert_eq!(other.unwrap(), scenario);
+        }
+        scenario.insert(
+            "adversary",
+            ScenarioValue::Values(vec![ScenarioValue::Flag(true)]),
+        );
+        assert!(scenario.to_args().is_err());
+    }
+}
@@ -0,0 +1,190 @@
+use {
+    crate::{
+        gossip::{Config, Node, Packet},
+        Error, Router,
+    },
+    rand::Rng,
+    solana_sdk::pubkey::Pubkey,
+    std::{
+        cmp::Reverse,
+        collections::{BinaryHeap, HashMap},
+        sync::Arc,
+        time::{Duration, Instant},
+    },
+};
+
+// Discrete-event scheduler which runs gossip rounds in virtual time.
+// Each node runs a gossip round every `gossip_interval`, and packets become
+// visible to the receiving node once the virtual clock passes their delivery
+// time. Given the same seed, a run is reproducible bit-for-bit regardless of
+// host cpu speed.
+pub struct Scheduler {
+    // Virtual time is anchored at an arbitrary instant so that nodes and the
+    // router can use the same time type as in wall-clock simulations.
+    epoch: Instant,
+    // Virtual time elapsed since the epoch.
+    now: Duration,
+    gossip_interval: Duration,
+    // Next gossip round of each node, keyed by (virtual time, node index).
+    queue: BinaryHeap<Reverse<(Duration, usize)>>,
+}
+
+impl Scheduler {
+    pub fn new<R: Rng>(
+        rng: &mut R,
+        epoch: Instant,
+        num_nodes: usize,
+        gossip_interval: Duration,
+    ) -> Self {
+        assert!(gossip_interval > Duration::ZERO);
+        // Stagger the first gossip round of each node uniformly across one
+        // interval so that nodes do not run in lockstep.
+        let queue = (0..num_nodes)
+            .map(|index| Reverse((gossip_interval.mul_f64(rng.gen()), index)))
+            .collect();
+        Self {
+            epoch,
+            now: Duration::ZERO,
+            gossip_interval,
+            queue,
+        }
+    }
+
+    pub fn now(&self) -> Instant {
+        self.epoch + self.now
+    }
+
+    // Virtual time elapsed since the epoch.
+    pub fn elapsed(&self) -> Duration {
+        self.now
+    }
+
+    // Runs gossip rounds in virtual time until config.run_duration elapses.
+    pub fn run<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        config: &Config,
+        nodes: &mut [Node],
+        stakes: &HashMap<Pubkey, u64>,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        self.run_until(rng, config.run_duration, config, nodes, stakes, router)
+    }
+
+    // Runs gossip rounds in virtual time until the given time since the epoch,
+    // so that the caller can inspect nodes in between.
+    pub fn run_until<R: Rng>(
+        &mut self,
+        rng: &mut R,
+        until: Duration,
+        config: &Config,
+        nodes: &mut [Node],
+        stakes: &HashMap<Pubkey, u64>,
+        router: &Router<Arc<Packet>>,
+    ) -> Result<(), Error> {
+        while let Some(&Reverse((time, index))) = self.queue.peek() {
+            if time >= until {
+                break;
+            }
+            self.queue.pop();
+            self.now = time;
+            nodes[index].run_gossip(rng, self.now(), config, stakes, router)?;
+            self.queue
+                .push(Reverse((time + self.gossip_interval, index)));
+        }
+        self.now = self.now.max(until);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            gossip::{get_crds_table, CrdsKey},
+            latency::Latency,
+        },
+        rand::SeedableRng,
+        rand_chacha::ChaChaRng,
+        solana_sdk::native_token::LAMPORTS_PER_SOL,
+        std::iter::repeat_with,
+    };
+
+    fn run_simulation(seed: [u8; 32]) -> Vec<(usize, HashMap<CrdsKey, u64>)> {
+        const NUM_NODES: usize = 32;
+        let mut rng = ChaChaRng::from_seed(seed);
+        let epoch = Instant::now();
+        let config = Config {
+            gossip_push_fanout: 3.0,
+            gossip_push_wide_fanout: 4.0,
+            rotate_active_set_rounds: 5,
+            gossip_prune_min_ingress_nodes: 2,
+            gossip_prune_stake_threshold: 0.15,
+            received_cache_min_num_upserts: 20,
+            received_cache_entry_capacity: 50,
+            received_cache_num_dups_threshold: 2,
+            push_active_set_size: 9,
+            num_push_active_set_entries: 25,
+            push_active_set_bloom_false_rate: 0.1,
+            push_active_set_bloom_max_bits: 1024 * 8 * 4,
+            gossip_push_capacity: 64,
+            packet_drop_rate: 0.1,
+            num_crds: 8,
+            refresh_rate: 0.5,
+            num_threads: 1,
+            run_duration: Duration::from_secs(10),
+            seed: 0,
+            virtual_time: true,
+            gossip_interval: Duration::from_millis(200),
+            warm_up_rounds: 0,
+            gossip_pull_rounds: 2,
+            gossip_pull_fanout: 1,
+            crds_timeout_staked: Duration::from_secs(172_800),
+            crds_timeout_unstaked: Duration::from_secs(15),
+            crds_purge_rounds: 5,
+            ping_cache_ttl: Duration::from_secs(1280),
+            ping_cache_rate_limit_delay: Duration::from_secs(20),
+        };
+        let pubkeys: Vec<_> = repeat_with(|| Pubkey::new_from_array(rng.gen()))
+            .take(NUM_NODES)
+            .collect();
+        let (mut nodes, senders): (Vec<_>, Vec<_>) = pubkeys
+            .iter()
+            .map(|&pubkey| {
+                let stake = rng.gen_range(0, 1_000_000) * LAMPORTS_PER_SOL;
+                let (node, sender) = Node::new(epoch, pubkey, stake);
+                (node, (pubkey, sender))
+            })
+            .unzip();
+        let stakes: HashMap<Pubkey, u64> = nodes
+            .iter()
+            .map(|node| (node.pubkey(), node.stake()))
+            .collect();
+        let latency = Latency::Uniform {
+            min: Duration::from_millis(10),
+            max: Duration::from_millis(300),
+        };
+        let router = Router::new(config.packet_drop_rate, latency, senders).unwrap();
+        let mut scheduler = Scheduler::new(&mut rng, epoch, nodes.len(), config.gossip_interval);
+        scheduler
+            .run(&mut rng, &config, &mut nodes, &stakes, &router)
+            .unwrap();
+        assert_eq!(scheduler.now(), epoch + config.run_duration);
+        nodes
+            .iter()
+            .map(|node| (node.num_gossip_rounds(), get_crds_table([node])))
+            .collect()
+    }
+
+    #[test]
+    fn test_scheduler_reproducible() {
+        let out = run_simulation([37u8; 32]);
+        // 10s run duration with 200ms gossip interval.
+        assert!(out.iter().all(|(num_rounds, _)| *num_rounds == 50));
+        // Gossip has propagated values across the cluster.
+        assert!(out.iter().all(|(_, table)| table.len() > 64));
+        assert_eq!(out, run_simulation([37u8; 32]));
+        assert_ne!(out, run_simulation([59u8; 32]));
+    }
+}
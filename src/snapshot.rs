@@ -0,0 +1,92 @@
+use {
+    crate::gossip::NodeInfo,
+    solana_sdk::pubkey::{ParsePubkeyError, Pubkey},
+    std::{
+        fs::File,
+        io::{BufRead, BufReader, BufWriter, Write},
+        num::ParseIntError,
+        path::Path,
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+const SNAPSHOT_HEADER: &str = "# pubkey,stake,shred_version";
+
+#[derive(Debug, Error)]
+pub enum SnapshotError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid snapshot line: {0}")]
+    InvalidLine(String),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+    #[error(transparent)]
+    ParsePubkeyError(#[from] ParsePubkeyError),
+}
+
+// Writes cluster nodes to a csv file with lines:
+//     pubkey,stake,shred-version
+// where shred-version is left empty if unknown.
+pub fn write_snapshot<P: AsRef<Path>>(path: P, nodes: &[NodeInfo]) -> Result<(), SnapshotError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{SNAPSHOT_HEADER}")?;
+    for node in nodes {
+        let shred_version = node
+            .shred_version
+            .map(|shred_version| shred_version.to_string())
+            .unwrap_or_default();
+        writeln!(writer, "{},{},{}", node.pubkey, node.stake, shred_version)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+pub fn read_snapshot<P: AsRef<Path>>(path: P) -> Result<Vec<NodeInfo>, SnapshotError> {
+    let mut nodes = Vec::new();
+    for line in BufReader::new(File::open(path)?).lines() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let fields: Vec<_> = line.split(',').map(str::trim).collect();
+        let [pubkey, stake, shred_version] = fields[..] else {
+            return Err(SnapshotError::InvalidLine(line.to_string()));
+        };
+        let shred_version = match shred_version {
+            "" => None,
+            shred_version => Some(u16::from_str(shred_version)?),
+        };
+        nodes.push(NodeInfo {
+            pubkey: Pubkey::from_str(pubkey)?,
+            stake: u64::from_str(stake)?,
+            shred_version,
+        });
+    }
+    Ok(nodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, std::iter::repeat_with};
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let nodes: Vec<_> = repeat_with(Pubkey::new_unique)
+            .take(7)
+            .enumerate()
+            .map(|(k, pubkey)| NodeInfo {
+                pubkey,
+                stake: (k as u64) * 1_000_000_007,
+                shred_version: (k % 3 != 0).then_some(50_093),
+            })
+            .collect();
+        let path =
+            std::env::temp_dir().join(format!("cluster-mocks-snapshot-{}.csv", std::process::id()));
+        write_snapshot(&path, &nodes).unwrap();
+        let other = read_snapshot(&path);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(other.unwrap(), nodes);
+    }
+}
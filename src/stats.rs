@@ -0,0 +1,485 @@
+use {
+    crate::{
+        gossip::{Config, Node},
+        propagation::{Histogram, PropagationStats},
+        scenario::{write_scenario, Scenario, ScenarioError},
+    },
+    serde::Serialize,
+    std::{
+        fs::File,
+        io::{BufWriter, Write},
+        path::Path,
+        str::FromStr,
+        time::Duration,
+    },
+    thiserror::Error,
+};
+
+// Csv columns shared by cluster-wide stats snapshots and per-node summary
+// rows, each prefixed with its own identifying columns.
+const STATS_CSV_COLUMNS: &str = "num_gossip_rounds,num_packets,num_push_values,num_prunes,\
+    num_pulls,num_outdated,num_duplicates,num_push_upserts,num_pull_upserts,num_pushes_sent,\
+    num_prunes_sent,num_pull_requests_sent,num_pull_responses_sent,active_set_size,\
+    num_egress_drops,num_ingress_drops,num_bytes_sent,num_bytes_received,num_purged,\
+    num_expired,table_size,num_pings_sent,num_push_drops,latency_p50_ms,latency_p90_ms,\
+    latency_p99_ms,queueing_delay_p50_ms,queueing_delay_p99_ms";
+
+#[derive(Debug, Error)]
+pub enum StatsError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("invalid stats format: {0}")]
+    InvalidFormat(String),
+    #[error(transparent)]
+    ScenarioError(#[from] ScenarioError),
+    #[error(transparent)]
+    SerdeJsonError(#[from] serde_json::Error),
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum StatsFormat {
+    Csv,
+    Json,
+}
+
+// Gossip counters of a node, accumulated after its warm-up rounds.
+#[derive(Clone, Copy, Debug, Default, Serialize)]
+pub struct NodeStats {
+    // Received packets, including prunes and pull packets.
+    pub num_packets: usize,
+    // Crds values received in push messages.
+    pub num_push_values: usize,
+    pub num_prunes: usize,
+    // Received pull requests and responses.
+    pub num_pulls: usize,
+    pub num_outdated: usize,
+    pub num_duplicates: usize,
+    // Number of crds values upserted from push messages and pull responses.
+    pub num_push_upserts: usize,
+    pub num_pull_upserts: usize,
+    // Push packets sent; each carries a batch of crds values.
+    pub num_pushes_sent: usize,
+    pub num_prunes_sent: usize,
+    pub num_pull_requests_sent: usize,
+    pub num_pull_responses_sent: usize,
+    // Number of distinct nodes in the push active set as of the last
+    // rotation. Unlike the counters, this is a gauge updated during warm-up
+    // rounds as well; cluster-wide stats hold its sum across nodes, which
+    // divided by num_nodes is the mean.
+    pub active_set_size: usize,
+    // Packets tail-dropped at a full egress or ingress queue.
+    pub num_egress_drops: usize,
+    pub num_ingress_drops: usize,
+    // Bytes sent and received on the wire.
+    pub num_bytes_sent: usize,
+    pub num_bytes_received: usize,
+    // Crds values purged from the table for exceeding the crds timeout, and
+    // received values rejected for the same reason.
+    pub num_purged: usize,
+    pub num_expired: usize,
+    // Number of crds table entries as of the node's last gossip round. A gauge
+    // like active_set_size, also summed across nodes in cluster-wide stats.
+    pub table_size: usize,
+    pub num_pings_sent: usize,
+    // Push packets dropped by packet loss, partitions or offline receivers,
+    // i.e. fanout slots wasted on unreachable peers.
+    pub num_push_drops: usize,
+}
+
+// Cluster-wide stats at a point in (virtual or wall-clock) time.
+#[derive(Clone, Debug, Serialize)]
+pub struct ClusterStats {
+    pub elapsed_ms: u64,
+    pub num_nodes: usize,
+    pub num_gossip_rounds: usize,
+    #[serde(flatten)]
+    pub stats: NodeStats,
+    pub latency_p50_ms: Option<u64>,
+    pub latency_p90_ms: Option<u64>,
+    pub latency_p99_ms: Option<u64>,
+    pub queueing_delay_p50_ms: Option<u64>,
+    pub queueing_delay_p99_ms: Option<u64>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct NodeSummary {
+    pubkey: String,
+    stake: u64,
+    num_gossip_rounds: usize,
+    #[serde(flatten)]
+    stats: NodeStats,
+    latency_p50_ms: Option<u64>,
+    latency_p90_ms: Option<u64>,
+    latency_p99_ms: Option<u64>,
+    queueing_delay_p50_ms: Option<u64>,
+    queueing_delay_p99_ms: Option<u64>,
+}
+
+#[derive(Serialize)]
+struct Summary<'a> {
+    scenario: &'a Scenario,
+    config: &'a Config,
+    cluster: &'a ClusterStats,
+    nodes: Vec<NodeSummary>,
+}
+
+// Writes periodic cluster stats snapshots either as csv rows or as json
+// lines.
+pub struct StatsWriter {
+    format: StatsFormat,
+    writer: BufWriter<File>,
+}
+
+impl NodeStats {
+    pub fn merge(&mut self, other: &Self) {
+        self.num_packets += other.num_packets;
+        self.num_push_values += other.num_push_values;
+        self.num_prunes += other.num_prunes;
+        self.num_pulls += other.num_pulls;
+        self.num_outdated += other.num_outdated;
+        self.num_duplicates += other.num_duplicates;
+        self.num_push_upserts += other.num_push_upserts;
+        self.num_pull_upserts += other.num_pull_upserts;
+        self.num_pushes_sent += other.num_pushes_sent;
+        self.num_prunes_sent += other.num_prunes_sent;
+        self.num_pull_requests_sent += other.num_pull_requests_sent;
+        self.num_pull_responses_sent += other.num_pull_responses_sent;
+        self.active_set_size += other.active_set_size;
+        self.num_egress_drops += other.num_egress_drops;
+        self.num_ingress_drops += other.num_ingress_drops;
+        self.num_bytes_sent += other.num_bytes_sent;
+        self.num_bytes_received += other.num_bytes_received;
+        self.num_purged += other.num_purged;
+        self.num_expired += other.num_expired;
+        self.table_size += other.table_size;
+        self.num_pings_sent += other.num_pings_sent;
+        self.num_push_drops += other.num_push_drops;
+    }
+}
+
+impl ClusterStats {
+    pub fn new<'a, I>(elapsed: Duration, nodes: I) -> Self
+    where
+        I: IntoIterator<Item = &'a Node>,
+    {
+        let mut num_nodes = 0;
+        let mut num_gossip_rounds = 0;
+        let mut stats = NodeStats::default();
+        let mut propagation = PropagationStats::default();
+        let mut queueing_delay = Histogram::default();
+        for node in nodes {
+            num_nodes += 1;
+            num_gossip_rounds += node.num_gossip_rounds();
+            stats.merge(node.stats());
+            propagation.merge(node.propagation());
+            queueing_delay.merge(node.queueing_delay());
+        }
+        let [latency_p50_ms, latency_p90_ms, latency_p99_ms] =
+            get_percentiles(&propagation.latency, [0.5, 0.9, 0.99]);
+        let [queueing_delay_p50_ms, queueing_delay_p99_ms] =
+            get_percentiles(&queueing_delay, [0.5, 0.99]);
+        Self {
+            elapsed_ms: elapsed.as_millis() as u64,
+            num_nodes,
+            num_gossip_rounds,
+            stats,
+            latency_p50_ms,
+            latency_p90_ms,
+            latency_p99_ms,
+            queueing_delay_p50_ms,
+            queueing_delay_p99_ms,
+        }
+    }
+
+    fn write_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{},{},", self.elapsed_ms, self.num_nodes)?;
+        write_stats_csv(
+            writer,
+            self.num_gossip_rounds,
+            &self.stats,
+            [
+                self.latency_p50_ms,
+                self.latency_p90_ms,
+                self.latency_p99_ms,
+                self.queueing_delay_p50_ms,
+                self.queueing_delay_p99_ms,
+            ],
+        )
+    }
+}
+
+impl NodeSummary {
+    fn new(node: &Node) -> Self {
+        let [latency_p50_ms, latency_p90_ms, latency_p99_ms] =
+            get_percentiles(&node.propagation().latency, [0.5, 0.9, 0.99]);
+        let [queueing_delay_p50_ms, queueing_delay_p99_ms] =
+            get_percentiles(node.queueing_delay(), [0.5, 0.99]);
+        Self {
+            pubkey: node.pubkey().to_string(),
+            stake: node.stake(),
+            num_gossip_rounds: node.num_gossip_rounds(),
+            stats: *node.stats(),
+            latency_p50_ms,
+            latency_p90_ms,
+            latency_p99_ms,
+            queueing_delay_p50_ms,
+            queueing_delay_p99_ms,
+        }
+    }
+
+    fn write_csv<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        write!(writer, "{},{},", self.pubkey, self.stake)?;
+        write_stats_csv(
+            writer,
+            self.num_gossip_rounds,
+            &self.stats,
+            [
+                self.latency_p50_ms,
+                self.latency_p90_ms,
+                self.latency_p99_ms,
+                self.queueing_delay_p50_ms,
+                self.queueing_delay_p99_ms,
+            ],
+        )
+    }
+}
+
+impl StatsWriter {
+    pub fn new<P: AsRef<Path>>(path: P, format: StatsFormat) -> Result<Self, StatsError> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        if format == StatsFormat::Csv {
+            writeln!(writer, "elapsed_ms,num_nodes,{STATS_CSV_COLUMNS}")?;
+        }
+        Ok(Self { format, writer })
+    }
+
+    pub fn write(&mut self, stats: &ClusterStats) -> Result<(), StatsError> {
+        match self.format {
+            StatsFormat::Csv => stats.write_csv(&mut self.writer)?,
+            StatsFormat::Json => {
+                serde_json::to_writer(&mut self.writer, stats)?;
+                writeln!(self.writer)?;
+            }
+        }
+        // Flush so that snapshots are visible while the simulation runs.
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+// Parses stats format from either of "csv" or "json".
+impl FromStr for StatsFormat {
+    type Err = StatsError;
+
+    fn from_str(format: &str) -> Result<Self, Self::Err> {
+        match format {
+            "csv" => Ok(Self::Csv),
+            "json" => Ok(Self::Json),
+            _ => Err(StatsError::InvalidFormat(format.to_string())),
+        }
+    }
+}
+
+// Writes the final summary including cluster-wide and per-node stats. The
+// csv format has one row per node, with the same stats columns as the
+// cluster-wide snapshots, which are their sum, and the scenario is written
+// next to it with a .scenario.toml extension. The json format also includes
+// the scenario and config the stats were produced with.
+pub fn write_summary<P: AsRef<Path>>(
+    path: P,
+    format: StatsFormat,
+    scenario: &Scenario,
+    config: &Config,
+    cluster: &ClusterStats,
+    nodes: &[Node],
+) -> Result<(), StatsError> {
+    let path = path.as_ref();
+    let mut writer = BufWriter::new(File::create(path)?);
+    let nodes = nodes.iter().map(NodeSummary::new);
+    match format {
+        StatsFormat::Csv => {
+            // Csv rows have no room for the scenario, so it is written to a
+            // separate file which can be loaded with --config.
+            write_scenario(path.with_extension("scenario.toml"), scenario)?;
+            writeln!(writer, "pubkey,stake,{STATS_CSV_COLUMNS}")?;
+            for node in nodes {
+                node.write_csv(&mut writer)?;
+            }
+        }
+        StatsFormat::Json => {
+            let summary = Summary {
+                scenario,
+                config,
+                cluster,
+                nodes: nodes.collect(),
+            };
+            serde_json::to_writer_pretty(&mut writer, &summary)?;
+            writeln!(writer)?;
+        }
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+// Writes the STATS_CSV_COLUMNS of a csv row, given latency and queueing delay
+// percentiles in the order of the columns.
+fn write_stats_csv<W: Write>(
+    writer: &mut W,
+    num_gossip_rounds: usize,
+    stats: &NodeStats,
+    percentiles: [Option<u64>; 5],
+) -> std::io::Result<()> {
+    let [latency_p50_ms, latency_p90_ms, latency_p99_ms, queueing_delay_p50_ms, queueing_delay_p99_ms] =
+        percentiles.map(|value| value.map(|value| value.to_string()).unwrap_or_default());
+    writeln!(
+        writer,
+        "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+        num_gossip_rounds,
+        stats.num_packets,
+        stats.num_push_values,
+        stats.num_prunes,
+        stats.num_pulls,
+        stats.num_outdated,
+        stats.num_duplicates,
+        stats.num_push_upserts,
+        stats.num_pull_upserts,
+        stats.num_pushes_sent,
+        stats.num_prunes_sent,
+        stats.num_pull_requests_sent,
+        stats.num_pull_responses_sent,
+        stats.active_set_size,
+        stats.num_egress_drops,
+        stats.num_ingress_drops,
+        stats.num_bytes_sent,
+        stats.num_bytes_received,
+        stats.num_purged,
+        stats.num_expired,
+        stats.table_size,
+        stats.num_pings_sent,
+        stats.num_push_drops,
+        latency_p50_ms,
+        latency_p90_ms,
+        latency_p99_ms,
+        queueing_delay_p50_ms,
+        queueing_delay_p99_ms,
+    )
+}
+
+fn get_percentiles<const N: usize>(histogram: &Histogram, fractions: [f64; N]) -> [Option<u64>; N] {
+    fractions.map(|fraction| {
+        let value = histogram.percentile(fraction)?;
+        Some(value.as_millis() as u64)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use {
+        super::*,
+        crate::{
+            gossip::tests::make_config,
+            scenario::{read_scenario, ScenarioValue},
+        },
+    };
+
+    #[test]
+    fn test_stats_writer() {
+        let stats = ClusterStats {
+            elapsed_ms: 2_000,
+            num_nodes: 3,
+            num_gossip_rounds: 30,
+            stats: NodeStats {
+                num_packets: 17,
+                num_prunes: 2,
+                num_pushes_sent: 23,
+                active_set_size: 12,
+                num_bytes_sent: 4096,
+                ..NodeStats::default()
+            },
+            latency_p50_ms: Some(150),
+            latency_p90_ms: Some(420),
+            latency_p99_ms: None,
+            queueing_delay_p50_ms: Some(3),
+            queueing_delay_p99_ms: None,
+        };
+        let path = std::env::temp_dir().join(format!("cluster-mocks-stats-{}", std::process::id()));
+        let mut writer = StatsWriter::new(&path, StatsFormat::from_str("csv").unwrap()).unwrap();
+        writer.write(&stats).unwrap();
+        writer
+            .write(&ClusterStats::new(Duration::ZERO, &[]))
+            .unwrap();
+        drop(writer);
+        let lines = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            lines.lines().collect::<Vec<_>>(),
+            [
+                &format!("elapsed_ms,num_nodes,{STATS_CSV_COLUMNS}"),
+                "2000,3,30,17,0,2,0,0,0,0,0,23,0,0,0,12,0,0,4096,0,0,0,0,0,0,150,420,,3,",
+                "0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,0,,,,,"
+            ]
+        );
+        // Per-node summary rows line up with the same stats columns.
+        let node = NodeSummary {
+            pubkey: "node".to_string(),
+            stake: 42,
+            num_gossip_rounds: 30,
+            stats: stats.stats,
+            latency_p50_ms: None,
+            latency_p90_ms: Some(420),
+            latency_p99_ms: None,
+            queueing_delay_p50_ms: None,
+            queueing_delay_p99_ms: Some(7),
+        };
+        let mut row = Vec::default();
+        node.write_csv(&mut row).unwrap();
+        assert_eq!(
+            String::from_utf8(row).unwrap(),
+            "node,42,30,17,0,2,0,0,0,0,0,23,0,0,0,12,0,0,4096,0,0,0,0,0,0,,420,,,7\n"
+        );
+        assert_eq!(
+            format!("pubkey,stake,{STATS_CSV_COLUMNS}")
+                .split(',')
+                .count(),
+            lines.lines().nth(1).unwrap().split(',').count()
+        );
+        let mut writer = StatsWriter::new(&path, StatsFormat::Json).unwrap();
+        writer.write(&stats).unwrap();
+        drop(writer);
+        let lines = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&lines.unwrap()).unwrap();
+        assert_eq!(value["elapsed_ms"], 2_000);
+        assert_eq!(value["num_pushes_sent"], 23);
+        assert_eq!(value["latency_p90_ms"], 420);
+        assert!(value["latency_p99_ms"].is_null());
+        assert!(StatsFormat::from_str("xml").is_err());
+    }
+
+    #[test]
+    fn test_write_summary() {
+        let mut scenario = Scenario::default();
+        scenario.insert("gossip-push-fanout", ScenarioValue::Value("6".to_string()));
+        let config = make_config();
+        let cluster = ClusterStats::new(Duration::ZERO, &[]);
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("cluster-mocks-summary-{}.csv", std::process::id()));
+        write_summary(&path, StatsFormat::Csv, &scenario, &config, &cluster, &[]).unwrap();
+        let lines = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(lines, format!("pubkey,stake,{STATS_CSV_COLUMNS}\n"));
+        // The effective scenario is written alongside csv stats.
+        let scenario_path = path.with_extension("scenario.toml");
+        let out = read_scenario(&scenario_path).unwrap();
+        std::fs::remove_file(&scenario_path).unwrap();
+        assert_eq!(out, scenario);
+        // The json summary embeds the scenario and a loadable config.
+        let path = path.with_extension("json");
+        write_summary(&path, StatsFormat::Json, &scenario, &config, &cluster, &[]).unwrap();
+        let data = std::fs::read_to_string(&path);
+        std::fs::remove_file(&path).unwrap();
+        let value: serde_json::Value = serde_json::from_str(&data.unwrap()).unwrap();
+        assert_eq!(value["scenario"]["gossip_push_fanout"], "6");
+        let out: Config = serde_json::from_value(value["config"].clone()).unwrap();
+        assert_eq!(format!("{out:?}"), format!("{config:?}"));
+    }
+}
@@ -0,0 +1,251 @@
+use {
+    crate::{
+        gossip::NodeInfo,
+        snapshot::{read_snapshot, SnapshotError},
+    },
+    rand::Rng,
+    solana_sdk::{native_token::LAMPORTS_PER_SOL, pubkey::Pubkey},
+    std::{
+        num::{ParseFloatError, ParseIntError},
+        str::FromStr,
+    },
+    thiserror::Error,
+};
+
+#[derive(Debug, Error)]
+pub enum StakeDistributionError {
+    #[error("invalid stake distribution spec: {0}")]
+    InvalidSpec(String),
+    #[error("invalid unstaked fraction: {0}")]
+    InvalidUnstakedFraction(f64),
+    #[error("no staked nodes in snapshot")]
+    NoStakedNodes,
+    #[error(transparent)]
+    ParseFloatError(#[from] ParseFloatError),
+    #[error(transparent)]
+    ParseIntError(#[from] ParseIntError),
+    #[error(transparent)]
+    SnapshotError(#[from] SnapshotError),
+}
+
+// Distribution of stakes (in SOL) among staked nodes of a synthetic cluster.
+#[derive(Clone, Debug, PartialEq)]
+pub enum StakeDistribution {
+    // Uniformly distributed in [min, max].
+    Uniform { min: u64, max: u64 },
+    // Power-law with P(stake > x) = (min / x)^alpha for x >= min.
+    Pareto { min: u64, alpha: f64 },
+    // Rank-size law where the k-th largest stake is max / k^exponent.
+    Zipf { max: u64, exponent: f64 },
+    // Resamples stakes (in lamports) observed in a cluster snapshot, so that
+    // clusters of any size have a mainnet-like shape.
+    Empirical(/*sorted stakes:*/ Vec<u64>),
+}
+
+impl StakeDistribution {
+    pub fn from_snapshot(nodes: &[NodeInfo]) -> Result<Self, StakeDistributionError> {
+        let mut stakes: Vec<u64> = nodes
+            .iter()
+            .map(|node| node.stake)
+            .filter(|&stake| stake != 0)
+            .collect();
+        if stakes.is_empty() {
+            return Err(StakeDistributionError::NoStakedNodes);
+        }
+        stakes.sort_unstable();
+        Ok(Self::Empirical(stakes))
+    }
+
+    // Returns stakes in lamports for the given number of staked nodes.
+    fn sample<R: Rng>(&self, rng: &mut R, num_nodes: usize) -> Vec<u64> {
+        let sol = |stake: f64| (stake * LAMPORTS_PER_SOL as f64) as u64;
+        match self {
+            Self::Uniform { min, max } => (0..num_nodes)
+                .map(|_| rng.gen_range(*min, max + 1) * LAMPORTS_PER_SOL)
+                .collect(),
+            Self::Pareto { min, alpha } => (0..num_nodes)
+                .map(|_| {
+                    let u = 1.0 - rng.gen::<f64>(); // in (0, 1]
+                    sol(*min as f64 / u.powf(1.0 / alpha))
+                })
+                .collect(),
+            Self::Zipf { max, exponent } => (1..=num_nodes)
+                .map(|k| sol(*max as f64 / (k as f64).powf(*exponent)))
+                .collect(),
+            Self::Empirical(stakes) => (0..num_nodes)
+                .map(|_| {
+                    // Linear interpolation between observed quantiles.
+                    let index = rng.gen::<f64>() * (stakes.len() - 1) as f64;
+                    let (lo, hi) = (stakes[index as usize], stakes[index.ceil() as usize]);
+                    lo + ((hi - lo) as f64 * index.fract()) as u64
+                })
+                .collect(),
+        }
+    }
+}
+
+// Parses stake distribution specs of the form:
+//     uniform:MIN,MAX | pareto:MIN,ALPHA | zipf:MAX,EXPONENT | snapshot:PATH
+// where stakes are in SOL, and uniform stakes are at most u64::MAX lamports.
+impl FromStr for StakeDistribution {
+    type Err = StakeDistributionError;
+
+    fn from_str(spec: &str) -> Result<Self, Self::Err> {
+        let invalid_spec = || StakeDistributionError::InvalidSpec(spec.to_string());
+        let (kind, args) = spec.split_once(':').ok_or_else(invalid_spec)?;
+        let args: Vec<_> = args.split(',').map(str::trim).collect();
+        match (kind, &args[..]) {
+            ("uniform", [min, max]) => {
+                let (min, max) = (u64::from_str(min)?, u64::from_str(max)?);
+                if min > max || max > u64::MAX / LAMPORTS_PER_SOL {
+                    return Err(invalid_spec());
+                }
+                Ok(Self::Uniform { min, max })
+            }
+            ("pareto", [min, alpha]) => {
+                let (min, alpha) = (u64::from_str(min)?, f64::from_str(alpha)?);
+                if min == 0 || !alpha.is_finite() || alpha <= 0.0 {
+                    return Err(invalid_spec());
+                }
+                Ok(Self::Pareto { min, alpha })
+            }
+            ("zipf", [max, exponent]) => {
+                let (max, exponent) = (u64::from_str(max)?, f64::from_str(exponent)?);
+                if !exponent.is_finite() || exponent < 0.0 {
+                    return Err(invalid_spec());
+                }
+                Ok(Self::Zipf { max, exponent })
+            }
+            ("snapshot", [path]) => Self::from_snapshot(&read_snapshot(path)?),
+            _ => Err(invalid_spec()),
+        }
+    }
+}
+
+// Parses the fraction of unstaked nodes in a synthetic cluster, which must be
+// in [0, 1].
+pub fn parse_unstaked_fraction(arg: &str) -> Result<f64, StakeDistributionError> {
+    let unstaked_fraction = f64::from_str(arg.trim())?;
+    if !(0.0..=1.0).contains(&unstaked_fraction) {
+        return Err(StakeDistributionError::InvalidUnstakedFraction(
+            unstaked_fraction,
+        ));
+    }
+    Ok(unstaked_fraction)
+}
+
+// Generates a cluster of random node pubkeys where the given fraction of
+// nodes is unstaked and the rest have stakes drawn from the distribution.
+pub fn make_synthetic_nodes<R: Rng>(
+    rng: &mut R,
+    num_nodes: usize,
+    distribution: &StakeDistribution,
+    unstaked_fraction: f64,
+) -> Vec<NodeInfo> {
+    assert!((0.0..=1.0).contains(&unstaked_fraction));
+    let num_unstaked = (num_nodes as f64 * unstaked_fraction).round() as usize;
+    let stakes = distribution.sample(rng, num_nodes - num_unstaked);
+    stakes
+        .into_iter()
+        .chain(std::iter::repeat(0).take(num_unstaked))
+        .map(|stake| NodeInfo {
+            pubkey: Pubkey::new_from_array(rng.gen()),
+            stake,
+            shred_version: None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use {super::*, rand::SeedableRng, rand_chacha::ChaChaRng};
+
+    #[test]
+    fn test_parse_stake_distribution() {
+        assert_eq!(
+            StakeDistribution::from_str("uniform:10,2000").unwrap(),
+            StakeDistribution::Uniform { min: 10, max: 2000 }
+        );
+        let max = u64::MAX / LAMPORTS_PER_SOL;
+        assert_eq!(
+            StakeDistribution::from_str(&format!("uniform:{max},{max}")).unwrap(),
+            StakeDistribution::Uniform { min: max, max }
+        );
+        assert_eq!(
+            StakeDistribution::from_str("pareto:1000,1.2").unwrap(),
+            StakeDistribution::Pareto {
+                min: 1000,
+                alpha: 1.2
+            }
+        );
+        assert_eq!(
+            StakeDistribution::from_str("zipf:5000000,0.8").unwrap(),
+            StakeDistribution::Zipf {
+                max: 5_000_000,
+                exponent: 0.8
+            }
+        );
+        for spec in [
+            "",
+            "uniform:7,5",
+            "uniform:0,18446744073709551615",
+            "uniform:0,18446744074",
+            "pareto:0,1",
+            "pareto:10,0",
+            "zipf:10",
+            "x:1",
+        ] {
+            assert!(StakeDistribution::from_str(spec).is_err(), "{spec}");
+        }
+        assert_eq!(parse_unstaked_fraction("0.25").unwrap(), 0.25);
+        assert_eq!(parse_unstaked_fraction("1").unwrap(), 1.0);
+        for arg in ["", "-0.1", "1.5", "NaN", "inf"] {
+            assert!(parse_unstaked_fraction(arg).is_err(), "{arg}");
+        }
+    }
+
+    #[test]
+    fn test_make_synthetic_nodes() {
+        let mut rng = ChaChaRng::from_seed([23u8; 32]);
+        let distribution = StakeDistribution::Pareto {
+            min: 1000,
+            alpha: 1.2,
+        };
+        let nodes = make_synthetic_nodes(&mut rng, 1000, &distribution, 0.25);
+        assert_eq!(nodes.len(), 1000);
+        assert_eq!(nodes.iter().filter(|node| node.stake == 0).count(), 250);
+        assert!(nodes[..750]
+            .iter()
+            .all(|node| node.stake >= 1000 * LAMPORTS_PER_SOL));
+        // Resampling from the generated cluster preserves the range of stakes.
+        let distribution = StakeDistribution::from_snapshot(&nodes).unwrap();
+        let StakeDistribution::Empirical(stakes) = &distribution else {
+            panic!("not empirical");
+        };
+        assert_eq!(stakes.len(), 750);
+        let (min, max) = (stakes[0], stakes[749]);
+        let other = make_synthetic_nodes(&mut rng, 3000, &distribution, 0.0);
+        assert!(other.iter().all(|node| (min..=max).contains(&node.stake)));
+        let distribution = StakeDistribution::Zipf {
+            max: 1_000_000,
+            exponent: 1.0,
+        };
+        let nodes = make_synthetic_nodes(&mut rng, 4, &distribution, 0.0);
+        assert_eq!(
+            nodes.iter().map(|node| node.stake).collect::<Vec<_>>(),
+            [
+                1_000_000 * LAMPORTS_PER_SOL,
+                500_000 * LAMPORTS_PER_SOL,
+                333_333_333_333_333,
+                250_000 * LAMPORTS_PER_SOL,
+            ]
+        );
+        // The largest uniform stake does not overflow in lamports.
+        let max = u64::MAX / LAMPORTS_PER_SOL;
+        let distribution = StakeDistribution::Uniform { min: max, max };
+        let nodes = make_synthetic_nodes(&mut rng, 2, &distribution, 0.0);
+        assert!(nodes
+            .iter()
+            .all(|node| node.stake == max * LAMPORTS_PER_SOL));
+    }
+}